@@ -6,6 +6,8 @@ use std::{error::Error, fmt::Display};
 
 use libc::{c_int, c_void};
 
+use crate::BoxResult;
+
 #[repr(C)]
 struct AesKeyFfi([c_int; 4 * (14 + 1)], c_int);
 
@@ -26,14 +28,88 @@ impl Display for InternalKeyError {
     }
 }
 impl Error for InternalKeyError {}
+
+const AES_128_KEY_LEN: usize = 16;
+
+/// Key sizes supported by AES
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyBits {
+    Aes128,
+    Aes192,
+    Aes256,
+}
+
+impl KeyBits {
+    /// Key size in bits, as expected by `AES_set_encrypt_key`
+    pub fn bits(&self) -> c_int {
+        match self {
+            KeyBits::Aes128 => 128,
+            KeyBits::Aes192 => 192,
+            KeyBits::Aes256 => 256,
+        }
+    }
+
+    /// Key size in bytes
+    pub fn key_len(&self) -> usize {
+        self.bits() as usize / 8
+    }
+}
+
+/// The key length found, and the one expected
+#[derive(Debug, PartialEq)]
+pub struct InvalidKeyLength(usize, usize);
+
+impl Display for InvalidKeyLength {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Invalid AES key length {}, expected {}", self.0, self.1)
+    }
+}
+impl Error for InvalidKeyLength {}
+
+/// A key with the right length for AES-128. openssl reads as many bytes as the key size,
+/// whatever the length of the buffer, so the length must be checked before calling it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aes128Key(pub [u8; AES_128_KEY_LEN]);
+
+impl TryFrom<&[u8]> for Aes128Key {
+    type Error = InvalidKeyLength;
+
+    fn try_from(key_data: &[u8]) -> Result<Aes128Key, InvalidKeyLength> {
+        key_data
+            .try_into()
+            .map(Aes128Key)
+            .map_err(|_| InvalidKeyLength(key_data.len(), AES_128_KEY_LEN))
+    }
+}
+
+impl From<&[u8; AES_128_KEY_LEN]> for Aes128Key {
+    fn from(key_data: &[u8; AES_128_KEY_LEN]) -> Aes128Key {
+        Aes128Key(*key_data)
+    }
+}
+
+// openssl reads as many bytes as the key size, whatever the length of the buffer
+fn check_key_len(key_data: &[u8], bits: KeyBits) -> Result<(), InvalidKeyLength> {
+    if key_data.len() != bits.key_len() {
+        return Err(InvalidKeyLength(key_data.len(), bits.key_len()));
+    }
+    Ok(())
+}
+
 pub struct AesKeyDecrypt(AesKeyFfi);
 
 impl AesKeyDecrypt {
-    pub fn new(key_data: &[u8]) -> Result<AesKeyDecrypt, InternalKeyError> {
+    pub fn new(key_data: &Aes128Key) -> BoxResult<AesKeyDecrypt> {
+        AesKeyDecrypt::with_bits(&key_data.0, KeyBits::Aes128)
+    }
+
+    /// Key of any AES size, `key_data` must be `bits.key_len()` bytes long
+    pub fn with_bits(key_data: &[u8], bits: KeyBits) -> BoxResult<AesKeyDecrypt> {
+        check_key_len(key_data, bits)?;
         let mut key = AesKeyFfi::new();
-        let ret = unsafe { AES_set_decrypt_key(key_data.as_ptr(), 128, &mut key) };
+        let ret = unsafe { AES_set_decrypt_key(key_data.as_ptr(), bits.bits(), &mut key) };
         if ret != 0 {
-            Err(InternalKeyError { code: ret })
+            Err(InternalKeyError { code: ret }.into())
         } else {
             Ok(AesKeyDecrypt(key))
         }
@@ -43,17 +119,68 @@ impl AesKeyDecrypt {
 pub struct AesKeyEncrypt(AesKeyFfi);
 
 impl AesKeyEncrypt {
-    pub fn new(key_data: &[u8]) -> Result<AesKeyEncrypt, InternalKeyError> {
+    pub fn new(key_data: &Aes128Key) -> BoxResult<AesKeyEncrypt> {
+        AesKeyEncrypt::with_bits(&key_data.0, KeyBits::Aes128)
+    }
+
+    /// Key of any AES size, `key_data` must be `bits.key_len()` bytes long
+    pub fn with_bits(key_data: &[u8], bits: KeyBits) -> BoxResult<AesKeyEncrypt> {
+        check_key_len(key_data, bits)?;
         let mut key = AesKeyFfi::new();
-        let ret = unsafe { AES_set_encrypt_key(key_data.as_ptr(), 128, &mut key) };
+        let ret = unsafe { AES_set_encrypt_key(key_data.as_ptr(), bits.bits(), &mut key) };
         if ret != 0 {
-            Err(InternalKeyError { code: ret })
+            Err(InternalKeyError { code: ret }.into())
         } else {
             Ok(AesKeyEncrypt(key))
         }
     }
 }
 
+impl AesKeyEncrypt {
+    /// The 11 round keys of the AES-128 key schedule, to compare the OpenSSL key expansion with
+    /// another implementation. `None` for AES-192 and AES-256 keys, or if the layout of the
+    /// OpenSSL round keys is unknown.
+    /// Debug API, only built with debug assertions and subject to change.
+    #[cfg(any(test, debug_assertions))]
+    pub fn round_keys(&self) -> Option<[[u8; 16]; 11]> {
+        // Number of rounds stored by OpenSSL after the round keys
+        if self.0 .1 != 10 {
+            return None;
+        }
+        let word_to_bytes = round_key_layout()?;
+        let mut round_keys = [[0u8; 16]; 11];
+        for (round_key, words) in round_keys.iter_mut().zip(self.0 .0.chunks_exact(4)) {
+            for (bytes, word) in round_key.chunks_exact_mut(4).zip(words) {
+                bytes.copy_from_slice(&word_to_bytes(*word as u32));
+            }
+        }
+        Some(round_keys)
+    }
+}
+
+#[cfg(any(test, debug_assertions))]
+type WordToBytes = fn(u32) -> [u8; 4];
+
+/// The assembly implementations of OpenSSL store the round keys as raw bytes, the portable C
+/// implementation as big-endian words. The first round key is the key itself, so expanding a
+/// known key once tells which layout is used.
+#[cfg(any(test, debug_assertions))]
+fn round_key_layout() -> Option<WordToBytes> {
+    static LAYOUT: std::sync::OnceLock<Option<WordToBytes>> = std::sync::OnceLock::new();
+    *LAYOUT.get_or_init(|| {
+        let probe: [u8; AES_128_KEY_LEN] = core::array::from_fn(|i| i as u8);
+        let key = AesKeyEncrypt::new(&(&probe).into()).ok()?;
+        let first_word = key.0 .0[0] as u32;
+        if first_word.to_ne_bytes() == probe[..4] {
+            Some(u32::to_ne_bytes as WordToBytes)
+        } else if first_word.to_be_bytes() == probe[..4] {
+            Some(u32::to_be_bytes as WordToBytes)
+        } else {
+            None
+        }
+    })
+}
+
 pub fn aes_encrypt(data_in: &[u8; 16], data_out: &mut [u8; 16], key: &AesKeyEncrypt) {
     unsafe {
         AES_encrypt(data_in.as_ptr(), data_out.as_mut_ptr(), &key.0);
@@ -66,6 +193,20 @@ pub fn aes_decrypt(data_in: &[u8; 16], data_out: &mut [u8; 16], key: &AesKeyDecr
     }
 }
 
+/// Same as `aes_encrypt`, returning the encrypted block
+pub fn aes_encrypt_block(data_in: &[u8; 16], key: &AesKeyEncrypt) -> [u8; 16] {
+    let mut data_out = [0u8; 16];
+    aes_encrypt(data_in, &mut data_out, key);
+    data_out
+}
+
+/// Same as `aes_decrypt`, returning the decrypted block
+pub fn aes_decrypt_block(data_in: &[u8; 16], key: &AesKeyDecrypt) -> [u8; 16] {
+    let mut data_out = [0u8; 16];
+    aes_decrypt(data_in, &mut data_out, key);
+    data_out
+}
+
 #[link(name = "crypto")]
 extern "C" {
     fn AES_set_encrypt_key(userKey: *const u8, bits: c_int, key: *mut AesKeyFfi) -> c_int;
@@ -80,8 +221,9 @@ extern "C" {
 #[cfg(test)]
 mod tests {
     use super::{
-        aes_decrypt, aes_encrypt, AES_decrypt, AES_encrypt, AES_set_decrypt_key,
-        AES_set_encrypt_key, AesKeyDecrypt, AesKeyEncrypt, AesKeyFfi,
+        aes_decrypt, aes_decrypt_block, aes_encrypt, aes_encrypt_block, AES_decrypt, AES_encrypt,
+        AES_set_decrypt_key, AES_set_encrypt_key, Aes128Key, AesKeyDecrypt, AesKeyEncrypt,
+        AesKeyFfi, InvalidKeyLength, KeyBits,
     };
 
     #[test]
@@ -119,12 +261,107 @@ mod tests {
         let plaintext = b"MELLOW TANGERINE";
         let mut ciphertext = [0u8; 16];
         let mut decoded_ciphertext = [0u8; 16];
-        let key_encrypt = AesKeyEncrypt::new(key_str).unwrap();
-        let key_decrypt = AesKeyDecrypt::new(key_str).unwrap();
-        aes_encrypt(&plaintext, &mut ciphertext, &key_encrypt);
+        let key_encrypt = AesKeyEncrypt::new(&key_str.into()).unwrap();
+        let key_decrypt = AesKeyDecrypt::new(&key_str.into()).unwrap();
+        aes_encrypt(plaintext, &mut ciphertext, &key_encrypt);
         aes_decrypt(&ciphertext, &mut decoded_ciphertext, &key_decrypt);
 
         assert_eq!(plaintext, &decoded_ciphertext);
         assert_ne!(plaintext, &ciphertext);
     }
+
+    #[test]
+    fn test_block_by_value() {
+        let key_encrypt = AesKeyEncrypt::new(&b"YELLOW SUBMARINE".into()).unwrap();
+        let key_decrypt = AesKeyDecrypt::new(&b"YELLOW SUBMARINE".into()).unwrap();
+        let plaintext = b"MELLOW TANGERINE";
+
+        let mut ciphertext = [0u8; 16];
+        aes_encrypt(plaintext, &mut ciphertext, &key_encrypt);
+        assert_eq!(aes_encrypt_block(plaintext, &key_encrypt), ciphertext);
+
+        let mut decrypted = [0u8; 16];
+        aes_decrypt(&ciphertext, &mut decrypted, &key_decrypt);
+        assert_eq!(aes_decrypt_block(&ciphertext, &key_decrypt), decrypted);
+        assert_eq!(&decrypted, plaintext);
+    }
+
+    #[test]
+    fn test_round_keys() {
+        let key = b"YELLOW SUBMARINE";
+        let round_keys = AesKeyEncrypt::new(&key.into())
+            .unwrap()
+            .round_keys()
+            .unwrap();
+        assert_eq!(&round_keys[0], key);
+
+        // FIPS-197, appendix A.1
+        let key = [
+            0x2b, 0x7e, 0x15, 0x16, 0x28, 0xae, 0xd2, 0xa6, 0xab, 0xf7, 0x15, 0x88, 0x09, 0xcf,
+            0x4f, 0x3c,
+        ];
+        let round_keys = AesKeyEncrypt::new(&(&key).into())
+            .unwrap()
+            .round_keys()
+            .unwrap();
+        assert_eq!(round_keys[0], key);
+        assert_eq!(
+            round_keys[10],
+            [
+                0xd0, 0x14, 0xf9, 0xa8, 0xc9, 0xee, 0x25, 0x89, 0xe1, 0x3f, 0x0c, 0xc8, 0xb6, 0x63,
+                0x0c, 0xa6
+            ]
+        );
+    }
+
+    #[test]
+    fn test_key_length() {
+        for key in [&b"too short!"[..], b"", b"YELLOW SUBMARINE!", &[0; 15]] {
+            assert_eq!(
+                Aes128Key::try_from(key),
+                Err(InvalidKeyLength(key.len(), 16))
+            );
+        }
+        let key = Aes128Key::try_from(&b"YELLOW SUBMARINE"[..]).unwrap();
+        assert_eq!(key, Aes128Key(*b"YELLOW SUBMARINE"));
+        assert_eq!(key, b"YELLOW SUBMARINE".into());
+        assert!(AesKeyEncrypt::new(&key).is_ok());
+        assert!(AesKeyDecrypt::new(&key).is_ok());
+    }
+
+    #[test]
+    fn test_key_bits() {
+        // FIPS-197, appendix C
+        let plaintext: [u8; 16] = core::array::from_fn(|i| (i * 0x11) as u8);
+        for (bits, expected) in [
+            (KeyBits::Aes128, "69c4e0d86a7b0430d8cdb78070b4c55a"),
+            (KeyBits::Aes192, "dda97ca4864cdfe06eaf70a0ec0d7191"),
+            (KeyBits::Aes256, "8ea2b7ca516745bfeafc49904b496089"),
+        ] {
+            let key_data: Vec<u8> = (0..bits.key_len() as u8).collect();
+            let mut key = AesKeyFfi::new();
+            unsafe {
+                assert_eq!(
+                    0,
+                    AES_set_encrypt_key(key_data.as_ptr(), bits.bits(), &mut key)
+                );
+            }
+
+            let key_encrypt = AesKeyEncrypt::with_bits(&key_data, bits).unwrap();
+            let key_decrypt = AesKeyDecrypt::with_bits(&key_data, bits).unwrap();
+            let ciphertext = aes_encrypt_block(&plaintext, &key_encrypt);
+            assert_eq!(ciphertext.to_vec(), crate::hex::from_hex(expected).unwrap());
+            assert_eq!(aes_decrypt_block(&ciphertext, &key_decrypt), plaintext);
+            assert_eq!(key_encrypt.round_keys().is_some(), bits == KeyBits::Aes128);
+
+            let error = AesKeyEncrypt::with_bits(&key_data[1..], bits)
+                .err()
+                .unwrap();
+            assert_eq!(
+                error.downcast_ref(),
+                Some(&InvalidKeyLength(bits.key_len() - 1, bits.key_len()))
+            );
+            assert!(AesKeyDecrypt::with_bits(&[0; 64], bits).is_err());
+        }
+    }
 }
@@ -1,8 +1,20 @@
 use std::{error::Error, fmt};
 
-use crate::block::{add_padding, xor, xor_inplace, BlockSize};
+use crate::base64::load_base64_file;
+use crate::block::{
+    add_padding, blocks_exact, blocks_exact_mut, xor_inplace, BlockSize, InvalidPadding, Padding,
+    StreamCipher, UnalignedData,
+};
+use crate::hmac::{constant_time_eq, hmac_sha1};
+use crate::kdf::derive_key_pbkdf2;
+use crate::BoxResult;
 
-use self::ffi_openssl::{aes_decrypt, aes_encrypt, AesKeyDecrypt, AesKeyEncrypt};
+/// Only exported for `AesKeyEncrypt::round_keys`, an unstable debug API
+pub use self::ffi_openssl::AesKeyEncrypt;
+use self::ffi_openssl::{
+    aes_decrypt, aes_decrypt_block, aes_encrypt, aes_encrypt_block, AesKeyDecrypt,
+};
+pub use self::ffi_openssl::{Aes128Key, InvalidKeyLength, KeyBits};
 mod ffi_openssl;
 use rand::prelude::*;
 
@@ -24,6 +36,24 @@ impl fmt::Display for DataTooLarge {
 
 impl Error for DataTooLarge {}
 
+#[derive(Debug, PartialEq)]
+pub struct DataTooShort {
+    got_size: usize,
+    min_size: usize,
+}
+
+impl fmt::Display for DataTooShort {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Data string too short: size {}, min {}",
+            self.got_size, self.min_size
+        )
+    }
+}
+
+impl Error for DataTooShort {}
+
 #[derive(Debug, PartialEq)]
 pub struct InvalidCiphertext(usize);
 
@@ -39,97 +69,656 @@ impl fmt::Display for InvalidCiphertext {
 
 impl Error for InvalidCiphertext {}
 
-// A lot of try_into to guarantee a known block size at the interface boundaries with ffi_openssl.
-// It doesn't feel "clean", I would love `chunks_exact(16)` to return `[u8;16]`, but alas that's
-// not supported by the type system...
+/// Checks that a ciphertext of length `len` is made of full blocks, at least one
+pub fn verify_ciphertext_alignment(len: usize, block_size: usize) -> Result<(), InvalidCiphertext> {
+    if len == 0 || !len.is_multiple_of(block_size) {
+        return Err(InvalidCiphertext(len));
+    }
+    Ok(())
+}
+
+#[derive(Debug)]
+pub struct NonPlaintext();
+
+impl fmt::Display for NonPlaintext {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Decrypted data does not look like plaintext, wrong key or IV?"
+        )
+    }
+}
+
+impl Error for NonPlaintext {}
+
+#[derive(Debug, PartialEq)]
+pub struct AuthenticationFailed();
+
+impl fmt::Display for AuthenticationFailed {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "The MAC does not match the data")
+    }
+}
+
+impl Error for AuthenticationFailed {}
 
-pub fn decrypt_cbc(
+pub fn decrypt_cbc(ciphertext: &[u8], iv: &[u8; 16], key: &[u8; 16]) -> BoxResult<Vec<u8>> {
+    decrypt_cbc_with_padding(ciphertext, iv, key, Padding::Pkcs7)
+}
+
+/// Same as `decrypt_cbc`, but fails with `NonPlaintext` when `validate` rejects the decrypted
+/// data. Handy to test candidate keys automatically.
+pub fn decrypt_cbc_checked(
     ciphertext: &[u8],
     iv: &[u8; 16],
     key: &[u8; 16],
-) -> Result<Vec<u8>, Box<dyn Error + 'static>> {
-    let mut last_cipher = iv;
-    let key = AesKeyDecrypt::new(key)?;
-    if ciphertext.len() % 16 != 0 || ciphertext.is_empty() {
-        return Err(InvalidCiphertext(ciphertext.len()).into());
+    validate: impl Fn(&[u8]) -> bool,
+) -> BoxResult<Vec<u8>> {
+    let plaintext = decrypt_cbc(ciphertext, iv, key)?;
+    if validate(&plaintext) {
+        Ok(plaintext)
+    } else {
+        Err(NonPlaintext().into())
     }
+}
+
+/// Same as `decrypt_cbc`, but an invalid padding is not an error: the whole decrypted buffer is
+/// returned as is, for forensics on corrupt ciphertexts.
+pub fn decrypt_cbc_lenient(ciphertext: &[u8], iv: &[u8; 16], key: &[u8; 16]) -> BoxResult<Vec<u8>> {
+    let mut plaintext = decrypt_cbc_with_padding(ciphertext, iv, key, Padding::NoPadding)?;
+    if let Ok(unpadded) = Padding::Pkcs7.unpad(&plaintext, BlockSize::AES_BLK_SZ) {
+        plaintext.truncate(unpadded.len());
+    }
+    Ok(plaintext)
+}
+
+/// Padding oracle of set 3 challenge 17: whether the ciphertext decrypts to a valid PKCS#7
+/// padding. Any other failure, like a misaligned ciphertext, is reported as `false`.
+pub fn cbc_padding_is_valid(ciphertext: &[u8], iv: &[u8; 16], key: &[u8; 16]) -> bool {
+    decrypt_cbc_with_padding(ciphertext, iv, key, Padding::NoPadding).is_ok_and(|plaintext| {
+        Padding::Pkcs7
+            .unpad(&plaintext, BlockSize::AES_BLK_SZ)
+            .is_ok()
+    })
+}
+
+pub fn decrypt_cbc_with_padding(
+    ciphertext: &[u8],
+    iv: &[u8; 16],
+    key: &[u8; 16],
+    padding: Padding,
+) -> BoxResult<Vec<u8>> {
+    let mut last_cipher = iv;
+    let key = AesKeyDecrypt::new(&key.into())?;
+    verify_ciphertext_alignment(ciphertext.len(), BlockSize::AES_BLK_SZ_USIZE)?;
 
     let mut plaintext = vec![0; ciphertext.len()];
 
-    for (plain_block, cipher_block) in plaintext
-        .chunks_exact_mut(BlockSize::AES_BLK_SZ_USIZE)
-        .zip(ciphertext.chunks(BlockSize::AES_BLK_SZ_USIZE))
+    for (plain_block, cipher_block) in
+        blocks_exact_mut(&mut plaintext).zip(blocks_exact(ciphertext))
     {
-        let cipher_block_16: &[u8; 16] = cipher_block.try_into()?;
-        aes_decrypt(cipher_block_16, plain_block.try_into()?, &key);
+        *plain_block = aes_decrypt_block(cipher_block, &key);
         xor_inplace(plain_block, last_cipher)?;
-        last_cipher = cipher_block_16;
+        last_cipher = cipher_block;
     }
 
-    // We know it's not going to be null because there has to be padding
-    let padding_len = plaintext[plaintext.len() - 1];
-    plaintext.resize(plaintext.len() - padding_len as usize, 0);
+    let unpadded_len = padding.unpad(&plaintext, BlockSize::AES_BLK_SZ)?.len();
+    plaintext.truncate(unpadded_len);
     Ok(plaintext)
 }
 
-pub fn encrypt_cbc(
+/// Same as `decrypt_cbc`, but the blocks are decrypted in parallel: each plaintext block only
+/// depends on its ciphertext block and the previous one.
+#[cfg(feature = "parallel")]
+pub fn decrypt_cbc_parallel(
+    ciphertext: &[u8],
+    iv: &[u8; 16],
+    key: &[u8; 16],
+) -> BoxResult<Vec<u8>> {
+    use rayon::prelude::*;
+
+    let key = AesKeyDecrypt::new(&key.into())?;
+    verify_ciphertext_alignment(ciphertext.len(), BlockSize::AES_BLK_SZ_USIZE)?;
+
+    let mut plaintext = vec![0; ciphertext.len()];
+
+    plaintext
+        .par_chunks_exact_mut(BlockSize::AES_BLK_SZ_USIZE)
+        .zip(ciphertext.par_chunks_exact(BlockSize::AES_BLK_SZ_USIZE))
+        .for_each(|(plain_block, cipher_block)| {
+            // par_chunks_exact guarantees the block size
+            aes_decrypt(
+                cipher_block.try_into().unwrap(),
+                plain_block.try_into().unwrap(),
+                &key,
+            )
+        });
+
+    let previous_blocks =
+        std::iter::once(iv.as_slice()).chain(blocks_exact(ciphertext).map(|b| b.as_slice()));
+    for (plain_block, previous_block) in blocks_exact_mut(&mut plaintext).zip(previous_blocks) {
+        xor_inplace(plain_block, previous_block)?;
+    }
+
+    let unpadded_len = Padding::Pkcs7
+        .unpad(&plaintext, BlockSize::AES_BLK_SZ)?
+        .len();
+    plaintext.truncate(unpadded_len);
+    Ok(plaintext)
+}
+
+pub fn encrypt_cbc(plaintext: &[u8], iv: &[u8; 16], key: &[u8; 16]) -> BoxResult<Vec<u8>> {
+    encrypt_cbc_with_padding(plaintext, iv, key, Padding::Pkcs7)
+}
+
+/// Counters collected while running a cipher
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CipherStats {
+    /// Number of blocks processed, padding included
+    pub blocks: usize,
+}
+
+/// Same as `encrypt_cbc`, also returning the number of blocks encrypted: `len / 16 + 1` with the
+/// PKCS#7 padding.
+pub fn encrypt_cbc_stats(
     plaintext: &[u8],
     iv: &[u8; 16],
     key: &[u8; 16],
-) -> Result<Vec<u8>, Box<dyn Error + 'static>> {
+) -> BoxResult<(Vec<u8>, CipherStats)> {
+    let ciphertext = encrypt_cbc(plaintext, iv, key)?;
+    let blocks = ciphertext.len() / BlockSize::AES_BLK_SZ_USIZE;
+    Ok((ciphertext, CipherStats { blocks }))
+}
+
+/// Decrypts then re-encrypts the ciphertext, and tells whether we get it back. A wrong key or IV
+/// almost always gives an invalid padding, which is reported as `false` rather than an error.
+/// Note that any key that gives a valid padding passes the check.
+pub fn roundtrip_cbc(ciphertext: &[u8], iv: &[u8; 16], key: &[u8; 16]) -> BoxResult<bool> {
+    let plaintext = match decrypt_cbc(ciphertext, iv, key) {
+        Ok(plaintext) => plaintext,
+        Err(e) if e.is::<InvalidPadding>() => return Ok(false),
+        Err(e) => return Err(e),
+    };
+    Ok(encrypt_cbc(&plaintext, iv, key)? == ciphertext)
+}
+
+/// CBC encryption with the given padding scheme. With `Padding::ZeroPad` and
+/// `Padding::NoPadding`, an empty plaintext gives an empty ciphertext.
+pub fn encrypt_cbc_with_padding(
+    plaintext: &[u8],
+    iv: &[u8; 16],
+    key: &[u8; 16],
+    padding: Padding,
+) -> BoxResult<Vec<u8>> {
     let mut last_cipher = *iv;
 
-    let plaintext = add_padding(&Vec::from(plaintext), BlockSize::AES_BLK_SZ)?;
+    let plaintext = padding.pad(plaintext, BlockSize::AES_BLK_SZ)?;
 
     let mut ciphertext = vec![0; plaintext.len()];
 
-    let key = AesKeyEncrypt::new(key)?;
-    for (plain_block, cipher_block) in plaintext
-        .chunks_exact(BlockSize::AES_BLK_SZ_USIZE)
-        .zip(ciphertext.chunks_exact_mut(16))
+    let key = AesKeyEncrypt::new(&key.into())?;
+    for (plain_block, cipher_block) in
+        blocks_exact(&plaintext).zip(blocks_exact_mut(&mut ciphertext))
     {
-        let cipher_block: &mut [u8; 16] = cipher_block.try_into()?;
-        let xored_block = xor(plain_block, &last_cipher)?;
-        aes_encrypt(&(*xored_block).try_into()?, cipher_block, &key);
+        let mut xored_block = *plain_block;
+        xor_inplace(&mut xored_block, &last_cipher)?;
+        aes_encrypt(&xored_block, cipher_block, &key);
         last_cipher = *cipher_block;
     }
 
     Ok(ciphertext)
 }
 
-pub fn encrypt_ecb(plaintext: &[u8], key: &[u8; 16]) -> Result<Vec<u8>, Box<dyn Error + 'static>> {
+/// CBC with ciphertext stealing (CS3 variant, as in Kerberos): no padding, the ciphertext has the
+/// same length as the plaintext. The last two ciphertext blocks are always swapped, and the last
+/// one is truncated to the length of the final plaintext block. Plaintexts shorter than a block
+/// are not supported.
+// https://nvlpubs.nist.gov/nistpubs/Legacy/SP/nist-special-publication-800-38a-add.pdf
+pub fn encrypt_cbc_cts(plaintext: &[u8], iv: &[u8; 16], key: &[u8; 16]) -> BoxResult<Vec<u8>> {
+    let block_size = BlockSize::AES_BLK_SZ_USIZE;
+    if plaintext.len() < block_size {
+        return Err(DataTooShort {
+            got_size: plaintext.len(),
+            min_size: block_size,
+        }
+        .into());
+    }
+
+    // Zero padding adds nothing to aligned data
+    let mut ciphertext = encrypt_cbc_with_padding(plaintext, iv, key, Padding::ZeroPad)?;
+    if ciphertext.len() > block_size {
+        let last_block_start = ciphertext.len() - block_size;
+        let (head, last_block) = ciphertext.split_at_mut(last_block_start);
+        head[last_block_start - block_size..].swap_with_slice(last_block);
+    }
+    ciphertext.truncate(plaintext.len());
+    Ok(ciphertext)
+}
+
+/// Decrypts the output of `encrypt_cbc_cts`
+pub fn decrypt_cbc_cts(ciphertext: &[u8], iv: &[u8; 16], key: &[u8; 16]) -> BoxResult<Vec<u8>> {
+    let block_size = BlockSize::AES_BLK_SZ_USIZE;
+    if ciphertext.len() < block_size {
+        return Err(DataTooShort {
+            got_size: ciphertext.len(),
+            min_size: block_size,
+        }
+        .into());
+    }
+    if ciphertext.len() == block_size {
+        return decrypt_cbc_with_padding(ciphertext, iv, key, Padding::NoPadding);
+    }
+
+    let number_of_blocks = ciphertext.len().div_ceil(block_size);
+    let last_len = ciphertext.len() - (number_of_blocks - 1) * block_size;
+    let (head, tail) = ciphertext.split_at((number_of_blocks - 2) * block_size);
+    // The last full block is the last block of the standard CBC ciphertext
+    let (last_block, stolen) = tail.split_at(block_size);
+
+    // The tail of the last plaintext block was zero-padded before encryption, so the decryption
+    // of the last block gives us the missing part of the second to last ciphertext block.
+    let decrypted_last =
+        aes_decrypt_block(last_block.try_into()?, &AesKeyDecrypt::new(&key.into())?);
+    let mut second_to_last = decrypted_last;
+    second_to_last[..last_len].copy_from_slice(stolen);
+    let mut last_plaintext = decrypted_last;
+    xor_inplace(&mut last_plaintext, &second_to_last)?;
+
+    let (mut plaintext, previous_block) = if head.is_empty() {
+        (vec![], *iv)
+    } else {
+        (
+            decrypt_cbc_with_padding(head, iv, key, Padding::NoPadding)?,
+            head[head.len() - block_size..].try_into()?,
+        )
+    };
+    plaintext.extend(decrypt_cbc_with_padding(
+        &second_to_last,
+        &previous_block,
+        key,
+        Padding::NoPadding,
+    )?);
+    plaintext.extend_from_slice(&last_plaintext[..last_len]);
+    Ok(plaintext)
+}
+
+/// CBC-MAC, the last block of the CBC encryption of the message without padding. The message
+/// must not be empty and must be aligned on the block size.
+pub fn cbc_mac(msg: &[u8], key: &[u8; 16], iv: &[u8; 16]) -> BoxResult<[u8; 16]> {
+    let ciphertext = encrypt_cbc_with_padding(msg, iv, key, Padding::NoPadding)?;
+    Ok(*ciphertext
+        .last_chunk::<16>()
+        .ok_or(InvalidCiphertext(ciphertext.len()))?)
+}
+
+/// If the attacker controls the IV sent with a message, they can replace the first block of the
+/// message without changing its CBC-MAC: the first block is only ever used as `iv ^ block`.
+/// Returns the IV to send along with the message starting with `forged_first_block`.
+pub fn forge_cbc_mac_iv(
+    iv: &[u8; 16],
+    original_first_block: &[u8; 16],
+    forged_first_block: &[u8; 16],
+) -> [u8; 16] {
+    let mut forged_iv = *iv;
+    for ((iv, original), forged) in forged_iv
+        .iter_mut()
+        .zip(original_first_block)
+        .zip(forged_first_block)
+    {
+        *iv ^= original ^ forged;
+    }
+    forged_iv
+}
+
+/// `plaintext` can be anything that derefs to bytes (`Vec<u8>`, arrays, slices...)
+pub fn encrypt_ecb(plaintext: impl AsRef<[u8]>, key: &[u8; 16]) -> BoxResult<Vec<u8>> {
+    encrypt_ecb_with_key(plaintext.as_ref(), &AesKeyEncrypt::new(&key.into())?)
+}
+
+/// ECB encryption of a block-aligned buffer in place, without padding. An empty buffer is fine.
+pub fn encrypt_ecb_inplace(buf: &mut [u8], key: &[u8; 16]) -> BoxResult<()> {
+    if !buf.len().is_multiple_of(BlockSize::AES_BLK_SZ_USIZE) {
+        return Err(UnalignedData(buf.len()).into());
+    }
+    let key = AesKeyEncrypt::new(&key.into())?;
+    for block in blocks_exact_mut(buf) {
+        *block = aes_encrypt_block(block, &key);
+    }
+    Ok(())
+}
+
+/// Inverse of `encrypt_ecb_inplace`, the padding is not removed. Like the other decryption
+/// functions, the ciphertext must have at least one block.
+pub fn decrypt_ecb_inplace(buf: &mut [u8], key: &[u8; 16]) -> BoxResult<()> {
+    verify_ciphertext_alignment(buf.len(), BlockSize::AES_BLK_SZ_USIZE)?;
+    let key = AesKeyDecrypt::new(&key.into())?;
+    for block in blocks_exact_mut(buf) {
+        *block = aes_decrypt_block(block, &key);
+    }
+    Ok(())
+}
+
+fn encrypt_ecb_with_key(plaintext: &[u8], key: &AesKeyEncrypt) -> BoxResult<Vec<u8>> {
     let plaintext = add_padding(&Vec::from(plaintext), BlockSize::AES_BLK_SZ)?;
     let mut ciphertext = vec![0; plaintext.len()];
 
-    let key = AesKeyEncrypt::new(key)?;
-    for (plain_block, cipher_block) in plaintext
-        .chunks_exact(BlockSize::AES_BLK_SZ_USIZE)
-        .zip(ciphertext.chunks_exact_mut(16))
+    for (plain_block, cipher_block) in
+        blocks_exact(&plaintext).zip(blocks_exact_mut(&mut ciphertext))
     {
-        let cipher_block: &mut [u8; 16] = cipher_block.try_into()?;
-        aes_encrypt(&(*plain_block).try_into()?, cipher_block, &key);
+        aes_encrypt(plain_block, cipher_block, key);
     }
 
     Ok(ciphertext)
 }
 
-pub fn decrypt_ecb(ciphertext: &[u8], key: &[u8; 16]) -> Result<Vec<u8>, Box<dyn Error + 'static>> {
+pub fn decrypt_ecb(ciphertext: impl AsRef<[u8]>, key: &[u8; 16]) -> BoxResult<Vec<u8>> {
+    decrypt_ecb_with_key(ciphertext.as_ref(), &AesKeyDecrypt::new(&key.into())?)
+}
+
+/// Decrypts a base64 challenge data file, see `load_base64_file`
+pub fn decrypt_ecb_file(file_id: &str, key: &[u8; 16]) -> BoxResult<Vec<u8>> {
+    decrypt_ecb(load_base64_file(file_id)?, key)
+}
+
+fn decrypt_ecb_with_key(ciphertext: &[u8], key: &AesKeyDecrypt) -> BoxResult<Vec<u8>> {
+    verify_ciphertext_alignment(ciphertext.len(), BlockSize::AES_BLK_SZ_USIZE)?;
     let mut plaintext = vec![0; ciphertext.len()];
 
-    let key = AesKeyDecrypt::new(key)?;
-    for (plain_block, cipher_block) in plaintext
-        .chunks_exact_mut(BlockSize::AES_BLK_SZ_USIZE)
-        .zip(ciphertext.chunks_exact(16))
+    for (plain_block, cipher_block) in
+        blocks_exact_mut(&mut plaintext).zip(blocks_exact(ciphertext))
     {
-        aes_decrypt(cipher_block.try_into()?, plain_block.try_into()?, &key);
+        aes_decrypt(cipher_block, plain_block, key);
     }
 
-    // We know it's not going to be null because there has to be padding
-    let padding_len = plaintext[plaintext.len() - 1];
-    plaintext.resize(plaintext.len() - padding_len as usize, 0);
+    // A wrong key gives garbage padding, which must be an error rather than a panic
+    let unpadded_len = Padding::Pkcs7
+        .unpad(&plaintext, BlockSize::AES_BLK_SZ)?
+        .len();
+    plaintext.truncate(unpadded_len);
     Ok(plaintext)
 }
 
+/// A block cipher mode with its key already set up, so that it can be handed
+/// around to the attacks as an encryption oracle.
+pub trait Cipher {
+    fn encrypt(&self, plaintext: &[u8]) -> BoxResult<Vec<u8>>;
+    fn decrypt(&self, ciphertext: &[u8]) -> BoxResult<Vec<u8>>;
+}
+
+/// AES-128 in ECB mode. The key schedules are computed once in `new`.
+pub struct AesEcb {
+    encrypt_key: AesKeyEncrypt,
+    decrypt_key: AesKeyDecrypt,
+}
+
+impl AesEcb {
+    pub fn new(key: &[u8; 16]) -> BoxResult<AesEcb> {
+        Ok(AesEcb {
+            encrypt_key: AesKeyEncrypt::new(&key.into())?,
+            decrypt_key: AesKeyDecrypt::new(&key.into())?,
+        })
+    }
+
+    /// Random key drawn from a generator seeded with `seed`, to reproduce a failing test.
+    pub fn from_seed(seed: u64) -> BoxResult<AesEcb> {
+        AesEcb::new(&StdRng::seed_from_u64(seed).gen())
+    }
+}
+
+impl Cipher for AesEcb {
+    fn encrypt(&self, plaintext: &[u8]) -> BoxResult<Vec<u8>> {
+        encrypt_ecb_with_key(plaintext, &self.encrypt_key)
+    }
+
+    fn decrypt(&self, ciphertext: &[u8]) -> BoxResult<Vec<u8>> {
+        decrypt_ecb_with_key(ciphertext, &self.decrypt_key)
+    }
+}
+
+/// AES-128 in CBC mode with a fixed key and IV, as used by the challenge oracles.
+#[derive(Debug, Clone)]
+pub struct Cbc {
+    pub key: [u8; 16],
+    pub iv: [u8; 16],
+}
+
+impl Cbc {
+    pub fn new(key: [u8; 16], iv: [u8; 16]) -> Cbc {
+        Cbc { key, iv }
+    }
+
+    pub fn with_random() -> Cbc {
+        Cbc::with_rng(&mut rand::thread_rng())
+    }
+
+    /// Same as `with_random`, with the key and IV drawn from `rng`
+    pub fn with_rng(rng: &mut impl Rng) -> Cbc {
+        Cbc {
+            key: rng.gen(),
+            iv: rng.gen(),
+        }
+    }
+
+    /// Random key and IV drawn from a generator seeded with `seed`, to reproduce a failing test.
+    pub fn from_seed(seed: u64) -> Cbc {
+        Cbc::with_rng(&mut StdRng::seed_from_u64(seed))
+    }
+}
+
+impl Cipher for Cbc {
+    fn encrypt(&self, plaintext: &[u8]) -> BoxResult<Vec<u8>> {
+        encrypt_cbc(plaintext, &self.iv, &self.key)
+    }
+
+    fn decrypt(&self, ciphertext: &[u8]) -> BoxResult<Vec<u8>> {
+        decrypt_cbc(ciphertext, &self.iv, &self.key)
+    }
+}
+
+/// Byte order used to serialize the nonce and the block counter in CTR mode.
+/// Cryptopals uses little-endian, most other tools use big-endian.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum CounterEndian {
+    #[default]
+    Little,
+    Big,
+}
+
+impl CounterEndian {
+    fn to_bytes(self, value: u64) -> [u8; 8] {
+        match self {
+            CounterEndian::Little => value.to_le_bytes(),
+            CounterEndian::Big => value.to_be_bytes(),
+        }
+    }
+}
+
+/// AES-CTR, each keystream block is the encryption of `nonce || counter` (both 64 bits).
+/// Encryption and decryption are the same operation.
+pub fn ctr(data: &[u8], key: &[u8; 16], nonce: u64, endian: CounterEndian) -> BoxResult<Vec<u8>> {
+    let mut output = ctr_keystream(key, nonce, endian, 0, data.len())?;
+    xor_inplace(&mut output, data)?;
+    Ok(output)
+}
+
+/// Returns `len` bytes of the CTR keystream, starting at byte `offset` of the stream.
+pub fn ctr_keystream(
+    key: &[u8; 16],
+    nonce: u64,
+    endian: CounterEndian,
+    offset: usize,
+    len: usize,
+) -> BoxResult<Vec<u8>> {
+    Ok(ctr_keystream_with_key(
+        &AesKeyEncrypt::new(&key.into())?,
+        nonce,
+        endian,
+        offset,
+        len,
+    ))
+}
+
+fn ctr_keystream_with_key(
+    key: &AesKeyEncrypt,
+    nonce: u64,
+    endian: CounterEndian,
+    offset: usize,
+    len: usize,
+) -> Vec<u8> {
+    let block_size = BlockSize::AES_BLK_SZ_USIZE;
+    let first_block = offset / block_size;
+    let skip = offset % block_size;
+    let number_of_blocks = (skip + len).div_ceil(block_size);
+
+    let mut keystream = vec![0u8; number_of_blocks * block_size];
+    let mut counter_block = [0u8; 16];
+    counter_block[..8].copy_from_slice(&endian.to_bytes(nonce));
+
+    for (counter, block) in (first_block..).zip(blocks_exact_mut(&mut keystream)) {
+        counter_block[8..].copy_from_slice(&endian.to_bytes(counter as u64));
+        aes_encrypt(&counter_block, block, key);
+    }
+
+    keystream[skip..skip + len].to_vec()
+}
+
+/// AES-128 in CTR mode, see `ctr`
+pub struct AesCtr {
+    key: AesKeyEncrypt,
+    nonce: u64,
+    endian: CounterEndian,
+}
+
+impl AesCtr {
+    pub fn new(key: &[u8; 16], nonce: u64, endian: CounterEndian) -> BoxResult<AesCtr> {
+        Ok(AesCtr {
+            key: AesKeyEncrypt::new(&key.into())?,
+            nonce,
+            endian,
+        })
+    }
+}
+
+impl StreamCipher for AesCtr {
+    fn keystream(&self, len: usize) -> Vec<u8> {
+        ctr_keystream_with_key(&self.key, self.nonce, self.endian, 0, len)
+    }
+}
+
+/// CTR-encrypts each plaintext with the same key and nonce (little-endian counter), which is
+/// the mistake exploited in challenges 19 and 20.
+pub fn encrypt_many_fixed_nonce(
+    plaintexts: &[&[u8]],
+    key: &[u8; 16],
+    nonce: u64,
+) -> BoxResult<Vec<Vec<u8>>> {
+    let cipher = AesCtr::new(key, nonce, CounterEndian::Little)?;
+    Ok(plaintexts
+        .iter()
+        .map(|plaintext| cipher.apply(plaintext))
+        .collect())
+}
+
+/// AES-128 in OFB mode, the keystream is the IV encrypted over and over
+pub struct AesOfb {
+    key: AesKeyEncrypt,
+    iv: [u8; 16],
+}
+
+impl AesOfb {
+    pub fn new(key: &[u8; 16], iv: &[u8; 16]) -> BoxResult<AesOfb> {
+        Ok(AesOfb {
+            key: AesKeyEncrypt::new(&key.into())?,
+            iv: *iv,
+        })
+    }
+}
+
+impl StreamCipher for AesOfb {
+    fn keystream(&self, len: usize) -> Vec<u8> {
+        let block_size = BlockSize::AES_BLK_SZ_USIZE;
+        let mut keystream = vec![0u8; len.div_ceil(block_size) * block_size];
+        let mut last_block = self.iv;
+        for block in blocks_exact_mut(&mut keystream) {
+            last_block = aes_encrypt_block(&last_block, &self.key);
+            *block = last_block;
+        }
+        keystream.truncate(len);
+        keystream
+    }
+}
+
+/// Encrypts with a random IV, which is sent along with the ciphertext: `iv || ciphertext`
+pub fn encrypt_cbc_prepend_iv(plaintext: &[u8], key: &[u8; 16]) -> BoxResult<Vec<u8>> {
+    let iv: [u8; 16] = rand::thread_rng().gen();
+    Ok([iv.as_slice(), &encrypt_cbc(plaintext, &iv, key)?].concat())
+}
+
+/// Decrypts the output of `encrypt_cbc_prepend_iv`
+pub fn decrypt_cbc_prepend_iv(data: &[u8], key: &[u8; 16]) -> BoxResult<Vec<u8>> {
+    if data.len() < 32 {
+        return Err(InvalidCiphertext(data.len()).into());
+    }
+    let (iv, ciphertext) = data.split_at(16);
+    decrypt_cbc(ciphertext, iv.try_into()?, key)
+}
+
+/// Number of PBKDF2 iterations used to derive the key in `encrypt_cbc_with_password`
+pub const PASSWORD_ITERATIONS: u32 = 4096;
+
+/// Encrypts with a key derived from the password and a random IV.
+/// The output is `salt || iv || ciphertext`.
+pub fn encrypt_cbc_with_password(
+    plaintext: &[u8],
+    password: &[u8],
+    salt: &[u8; 16],
+) -> BoxResult<Vec<u8>> {
+    let key = derive_key_pbkdf2(password, salt, PASSWORD_ITERATIONS);
+    let iv: [u8; 16] = rand::thread_rng().gen();
+    let ciphertext = encrypt_cbc(plaintext, &iv, &key)?;
+    Ok([salt.as_slice(), &iv, &ciphertext].concat())
+}
+
+/// Decrypts the output of `encrypt_cbc_with_password`
+pub fn decrypt_cbc_with_password(data: &[u8], password: &[u8]) -> BoxResult<Vec<u8>> {
+    if data.len() < 32 {
+        return Err(InvalidCiphertext(data.len()).into());
+    }
+    let (salt, data) = data.split_at(16);
+    let (iv, ciphertext) = data.split_at(16);
+    let key = derive_key_pbkdf2(password, salt, PASSWORD_ITERATIONS);
+    decrypt_cbc(ciphertext, iv.try_into()?, &key)
+}
+
+/// Length of the HMAC-SHA1 tag appended by `encrypt_then_mac`
+const MAC_LEN: usize = 20;
+
+/// Encrypt-then-MAC: CBC encryption with a random IV, authenticated with HMAC-SHA1.
+/// The output is `iv || ciphertext || hmac_sha1(mac_key, iv || ciphertext)`.
+pub fn encrypt_then_mac(
+    plaintext: &[u8],
+    enc_key: &[u8; 16],
+    mac_key: &[u8],
+) -> BoxResult<Vec<u8>> {
+    let mut data = encrypt_cbc_prepend_iv(plaintext, enc_key)?;
+    let mac = hmac_sha1(mac_key, &data);
+    data.extend_from_slice(&mac);
+    Ok(data)
+}
+
+/// Decrypts the output of `encrypt_then_mac`. The MAC is checked before anything is decrypted,
+/// so a tampered ciphertext gives `AuthenticationFailed` and never reaches the padding check.
+pub fn decrypt_and_verify(data: &[u8], enc_key: &[u8; 16], mac_key: &[u8]) -> BoxResult<Vec<u8>> {
+    if data.len() < 16 + MAC_LEN {
+        return Err(InvalidCiphertext(data.len()).into());
+    }
+    let (data, mac) = data.split_at(data.len() - MAC_LEN);
+    if !constant_time_eq(&hmac_sha1(mac_key, data), mac) {
+        return Err(AuthenticationFailed().into());
+    }
+    decrypt_cbc_prepend_iv(data, enc_key)
+}
+
 #[derive(PartialEq, Debug)]
 pub enum Protocol {
     Ecb,
@@ -137,9 +726,16 @@ pub enum Protocol {
 }
 
 /// This encrypts data using either CB or EBC (chosen at random).
-pub fn unknown_encryption(data: &[u8]) -> Result<(Protocol, Vec<u8>), Box<dyn Error + 'static>> {
-    let mut rng = rand::thread_rng();
+pub fn unknown_encryption(data: &[u8]) -> BoxResult<(Protocol, Vec<u8>)> {
+    unknown_encryption_with_rng(data, &mut rand::thread_rng())
+}
 
+/// Same as `unknown_encryption`, with all the randomness coming from `rng` so that a seeded
+/// generator gives reproducible results.
+pub fn unknown_encryption_with_rng(
+    data: &[u8],
+    rng: &mut impl Rng,
+) -> BoxResult<(Protocol, Vec<u8>)> {
     let mut gen_padding = |size_range| -> Vec<u8> {
         let size: i32 = rng.gen_range(size_range);
         (0..size).map(|_| rng.gen()).collect()
@@ -149,7 +745,7 @@ pub fn unknown_encryption(data: &[u8]) -> Result<(Protocol, Vec<u8>), Box<dyn Er
 
     let key = rng.gen();
 
-    if random::<bool>() {
+    if rng.gen::<bool>() {
         let iv = rng.gen();
         Ok((Protocol::Cbc, encrypt_cbc(&padded_data, &iv, &key)?))
     } else {
@@ -157,14 +753,25 @@ pub fn unknown_encryption(data: &[u8]) -> Result<(Protocol, Vec<u8>), Box<dyn Er
     }
 }
 
+/// Challenge 12 oracle: encrypts `data || secret` with ECB under a random key drawn once
+pub fn ecb_append_oracle(secret: Vec<u8>) -> impl Fn(&[u8]) -> BoxResult<Vec<u8>> {
+    let key: [u8; 16] = rand::thread_rng().gen();
+    move |data: &[u8]| encrypt_ecb([data, &secret].concat(), &key)
+}
+
 /// Oracle that can detect wether a function encodes data using ECB or CBC
 /// The function can add some padding at the beginning or at the end (less than 1 block)
 /// We send the same character enough time to be able to skip the padding and detect
 /// repeating block encryption (same input data -> same output means ECB)
 pub fn oracle(func: impl FnOnce(&[u8]) -> Vec<u8>) -> Protocol {
-    let test_data = b"a".repeat(16 * 3);
+    oracle_with_block_size(func, BlockSize::AES_BLK_SZ_USIZE)
+}
+
+/// Same as `oracle`, for a block cipher with blocks of `block_size` bytes
+pub fn oracle_with_block_size(func: impl FnOnce(&[u8]) -> Vec<u8>, block_size: usize) -> Protocol {
+    let test_data = b"a".repeat(block_size * 3);
     let encrypted_data = func(&test_data);
-    let mut blocks = encrypted_data.chunks(16).skip(1);
+    let mut blocks = encrypted_data.chunks(block_size).skip(1);
 
     let first = blocks
         .next()
@@ -179,6 +786,36 @@ pub fn oracle(func: impl FnOnce(&[u8]) -> Vec<u8>) -> Protocol {
     }
 }
 
+/// Same as `oracle`, but also returns a confidence: the fraction of adjacent blocks that are
+/// identical when encrypting a long run of the same character. ECB gives 1.0, CBC around 0.0.
+pub fn oracle_detailed(func: impl FnOnce(&[u8]) -> Vec<u8>) -> (Protocol, f32) {
+    const TEST_BLOCKS: usize = 8;
+    let test_data = b"a".repeat(16 * TEST_BLOCKS);
+    let encrypted_data = func(&test_data);
+
+    // The first block may contain some padding, and so may the last ones. Whatever the amount of
+    // padding (less than a block), the blocks 1 to TEST_BLOCKS - 1 are only made of test data.
+    let blocks: Vec<&[u8]> = encrypted_data
+        .chunks(16)
+        .skip(1)
+        .take(TEST_BLOCKS - 1)
+        .collect();
+    assert_eq!(
+        blocks.len(),
+        TEST_BLOCKS - 1,
+        "Invalid encryption method, output data is smaller than input data"
+    );
+
+    let matching_pairs = blocks.windows(2).filter(|w| w[0] == w[1]).count();
+    let confidence = matching_pairs as f32 / (blocks.len() - 1) as f32;
+
+    if confidence > 0.5 {
+        (Protocol::Ecb, confidence)
+    } else {
+        (Protocol::Cbc, confidence)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::aes::*;
@@ -200,6 +837,112 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_ecb_as_ref() {
+        let key = b"AZERTYUIOPASDFGH";
+        let expected = encrypt_ecb(b"YELLOW SUBMARINE".as_slice(), key).unwrap();
+
+        // Owned Vec and array, no borrowing needed
+        let plaintext: Vec<u8> = b"YELLOW SUBMARINE".to_vec();
+        assert_eq!(encrypt_ecb(plaintext, key).unwrap(), expected);
+        assert_eq!(encrypt_ecb(*b"YELLOW SUBMARINE", key).unwrap(), expected);
+        assert_eq!(decrypt_ecb(expected, key).unwrap(), b"YELLOW SUBMARINE");
+    }
+
+    #[test]
+    fn test_verify_ciphertext_alignment() {
+        assert_eq!(
+            verify_ciphertext_alignment(0, 16),
+            Err(InvalidCiphertext(0))
+        );
+        assert_eq!(
+            verify_ciphertext_alignment(15, 16),
+            Err(InvalidCiphertext(15))
+        );
+        assert_eq!(verify_ciphertext_alignment(16, 16), Ok(()));
+        assert_eq!(verify_ciphertext_alignment(32, 16), Ok(()));
+        assert_eq!(
+            verify_ciphertext_alignment(12, 8),
+            Err(InvalidCiphertext(12))
+        );
+
+        let key = b"AZERTYUIOPASDFGH";
+        for ciphertext in [vec![], vec![0; 15], vec![0; 17]] {
+            let error = decrypt_ecb(&ciphertext, key).unwrap_err();
+            assert!(error.is::<InvalidCiphertext>());
+            let error = AesEcb::new(key).unwrap().decrypt(&ciphertext).unwrap_err();
+            assert!(error.is::<InvalidCiphertext>());
+        }
+    }
+
+    #[test]
+    fn test_decrypt_ecb_file() {
+        let plaintext = decrypt_ecb_file("7", b"YELLOW SUBMARINE").unwrap();
+        assert!(plaintext.starts_with(b"I'm back and I'm ringin' the bell \n"));
+        assert_eq!(
+            plaintext,
+            decrypt_ecb(load_base64_file("7").unwrap(), b"YELLOW SUBMARINE").unwrap()
+        );
+
+        assert!(decrypt_ecb_file("7", b"YELLOW SUBMARINf").is_err());
+        assert!(decrypt_ecb_file("does_not_exist", b"YELLOW SUBMARINE").is_err());
+    }
+
+    #[test]
+    fn test_ecb_inplace() {
+        let key = b"YELLOW SUBMARINE";
+        let plaintext = b"YELLOW SUBMARINEMELLOW TANGERINEYELLOW SUBMARINE";
+        let mut buf = *plaintext;
+
+        encrypt_ecb_inplace(&mut buf, key).unwrap();
+        // Same blocks as the padded version, without the padding block
+        assert_eq!(buf, encrypt_ecb(plaintext, key).unwrap()[..48]);
+        assert_eq!(buf[..16], buf[32..]);
+
+        decrypt_ecb_inplace(&mut buf, key).unwrap();
+        assert_eq!(&buf, plaintext);
+
+        encrypt_ecb_inplace(&mut [], key).unwrap();
+        let mut unaligned = [0u8; 17];
+        let error = encrypt_ecb_inplace(&mut unaligned, key).unwrap_err();
+        assert!(error.is::<UnalignedData>());
+        let error = decrypt_ecb_inplace(&mut unaligned, key).unwrap_err();
+        assert!(error.is::<InvalidCiphertext>());
+        assert_eq!(unaligned, [0; 17]);
+        // Same error as decrypt_ecb
+        assert!(decrypt_ecb(unaligned, key)
+            .unwrap_err()
+            .is::<InvalidCiphertext>());
+        let error = decrypt_ecb_inplace(&mut [], key).unwrap_err();
+        assert!(error.is::<InvalidCiphertext>());
+    }
+
+    #[test]
+    fn test_aes_ecb() {
+        let key = b"AZERTYUIOPASDFGH";
+        let cipher = AesEcb::new(key).unwrap();
+
+        let ciphertext = cipher.encrypt(b"YELLOW SUBMARINEYELLOW SUBMARINE").unwrap();
+        assert_eq!(ciphertext.len(), 48);
+        assert_eq!(ciphertext[0..16], ciphertext[16..32]);
+        assert_ne!(ciphertext[16..32], ciphertext[32..48]);
+
+        assert_eq!(
+            ciphertext,
+            encrypt_ecb(b"YELLOW SUBMARINEYELLOW SUBMARINE", key).unwrap()
+        );
+        assert_eq!(
+            cipher.decrypt(&ciphertext).unwrap(),
+            b"YELLOW SUBMARINEYELLOW SUBMARINE"
+        );
+
+        let secret = b"Secret message";
+        let cracked =
+            crate::crack::crack_ecb(|data| cipher.encrypt(&[data, secret.as_slice()].concat()))
+                .unwrap();
+        assert_eq!(cracked, secret);
+    }
+
     #[test]
     fn test_cbc() {
         let iv = b"ivIVivIVivIVivIV";
@@ -221,21 +964,576 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_ctr() {
+        // Set 3 challenge 18
+        let ciphertext = crate::base64::from_base64(
+            "L77na/nrFsKvynd6HzOoG7GHTLXsTVu9qvY/2syLXzhPweyyMTJULu/6/kXX0KSvoOLSFQ==",
+        )
+        .unwrap();
+        let key = b"YELLOW SUBMARINE";
+        let expected = b"Yo, VIP Let's kick it Ice, Ice, baby Ice, Ice, baby ";
+
+        assert_eq!(
+            ctr(&ciphertext, key, 0, CounterEndian::Little).unwrap(),
+            expected
+        );
+        assert_eq!(
+            ctr(&ciphertext, key, 0, CounterEndian::default()).unwrap(),
+            expected
+        );
+
+        let big_endian = ctr(&ciphertext, key, 0, CounterEndian::Big).unwrap();
+        assert_eq!(big_endian.len(), expected.len());
+        assert_ne!(big_endian, expected);
+        // Only the first block matches since the counter 0 is the same in both byte orders
+        assert_eq!(big_endian[..16], expected[..16]);
+        assert_ne!(big_endian[16..], expected[16..]);
+        assert_eq!(
+            ctr(&big_endian, key, 0, CounterEndian::Big).unwrap(),
+            ciphertext
+        );
+
+        assert_ne!(
+            ctr(expected, key, 1, CounterEndian::Little).unwrap(),
+            ctr(expected, key, 1, CounterEndian::Big).unwrap()
+        );
+        assert!(ctr(b"", key, 0, CounterEndian::Little).unwrap().is_empty());
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn test_decrypt_cbc_parallel() {
+        let iv = b"ivIVivIVivIVivIV";
+        let key = b"AZERTYUIOPASDFGH";
+        for size in [0, 1, 15, 16, 17, 100, 4096, 100_000] {
+            let plaintext: Vec<u8> = (0..size).map(|i| (i * 7) as u8).collect();
+            let ciphertext = encrypt_cbc(&plaintext, iv, key).unwrap();
+            assert_eq!(
+                decrypt_cbc_parallel(&ciphertext, iv, key).unwrap(),
+                decrypt_cbc(&ciphertext, iv, key).unwrap()
+            );
+        }
+        assert!(decrypt_cbc_parallel(&[0; 5], iv, key).is_err());
+        assert!(decrypt_cbc_parallel(&[], iv, key).is_err());
+    }
+
+    #[test]
+    fn test_decrypt_cbc_checked() {
+        let key = b"YELLOW SUBMARINE";
+        let iv = b"0123456789ABCDEF";
+        let ascii = |data: &[u8]| data.is_ascii();
+        let ciphertext = encrypt_cbc(b"Some plain english text", iv, key).unwrap();
+
+        assert_eq!(
+            decrypt_cbc_checked(&ciphertext, iv, key, ascii).unwrap(),
+            b"Some plain english text"
+        );
+        assert!(decrypt_cbc_checked(&ciphertext, iv, b"ORANGE SUBMARINE", ascii).is_err());
+
+        // Valid padding, but the validator rejects the content
+        let ciphertext = encrypt_cbc(b"caf\xc3\xa9", iv, key).unwrap();
+        let error = decrypt_cbc_checked(&ciphertext, iv, key, ascii).unwrap_err();
+        assert!(error.downcast::<NonPlaintext>().is_ok());
+    }
+
+    #[test]
+    fn test_encrypt_cbc_stats() {
+        let key = b"YELLOW SUBMARINE";
+        let iv = &[0; 16];
+        let plaintext = [b'A'; 20];
+        let (ciphertext, stats) = encrypt_cbc_stats(&plaintext, iv, key).unwrap();
+        assert_eq!(stats, CipherStats { blocks: 2 });
+        assert_eq!(ciphertext, encrypt_cbc(&plaintext, iv, key).unwrap());
+
+        // A full block of padding
+        for (len, blocks) in [(0, 1), (15, 1), (16, 2), (32, 3)] {
+            let (_, stats) = encrypt_cbc_stats(&vec![0; len], iv, key).unwrap();
+            assert_eq!(stats.blocks, blocks);
+        }
+    }
+
+    #[test]
+    fn test_roundtrip_cbc() {
+        let ciphertext = crate::base64::load_base64_file("10").unwrap();
+        let iv = [0; 16];
+        assert!(roundtrip_cbc(&ciphertext, &iv, b"YELLOW SUBMARINE").unwrap());
+        assert!(!roundtrip_cbc(&ciphertext, &iv, b"ORANGE SUBMARINE").unwrap());
+        assert!(roundtrip_cbc(&ciphertext[..15], &iv, b"YELLOW SUBMARINE").is_err());
+    }
+
+    #[test]
+    fn test_cbc_cts() {
+        let key = b"YELLOW SUBMARINE";
+        let iv = b"0123456789ABCDEF";
+        let message: Vec<u8> = (0..48).collect();
+
+        for len in [16, 17, 31, 32, 33, 48] {
+            let ciphertext = encrypt_cbc_cts(&message[..len], iv, key).unwrap();
+            assert_eq!(ciphertext.len(), len);
+            assert_eq!(
+                decrypt_cbc_cts(&ciphertext, iv, key).unwrap(),
+                &message[..len]
+            );
+        }
+
+        // Aligned data: standard CBC with the last two blocks swapped
+        let cbc = encrypt_cbc_with_padding(&message, iv, key, Padding::NoPadding).unwrap();
+        let cts = encrypt_cbc_cts(&message, iv, key).unwrap();
+        assert_eq!(cts[..16], cbc[..16]);
+        assert_eq!(cts[16..32], cbc[32..]);
+        assert_eq!(cts[32..], cbc[16..32]);
+
+        // https://datatracker.ietf.org/doc/html/rfc3962#appendix-B
+        for (plaintext, ciphertext) in [
+            (
+                b"I would like the ".as_slice(),
+                "c6353568f2bf8cb4d8a580362da7ff7f97",
+            ),
+            (
+                b"I would like the General Gau's ",
+                "fc00783e0efdb2c1d445d4c8eff7ed2297687268d6ecccc0c07b25e25ecfe5",
+            ),
+        ] {
+            let ciphertext = crate::hex::from_hex(ciphertext).unwrap();
+            assert_eq!(
+                encrypt_cbc_cts(plaintext, &[0; 16], b"chicken teriyaki").unwrap(),
+                ciphertext
+            );
+            assert_eq!(
+                decrypt_cbc_cts(&ciphertext, &[0; 16], b"chicken teriyaki").unwrap(),
+                plaintext
+            );
+        }
+
+        assert!(encrypt_cbc_cts(&message[..15], iv, key).is_err());
+        assert!(decrypt_cbc_cts(&message[..15], iv, key).is_err());
+    }
+
+    #[test]
+    fn test_cbc_struct() {
+        let key = b"YELLOW SUBMARINE";
+        let iv = [0u8; 16];
+        let cipher = Cbc::new(*key, iv);
+
+        let ciphertext = cipher.encrypt(b"Hello, world").unwrap();
+        assert_eq!(ciphertext, encrypt_cbc(b"Hello, world", &iv, key).unwrap());
+        assert_eq!(cipher.decrypt(&ciphertext).unwrap(), b"Hello, world");
+
+        let random = Cbc::with_random();
+        assert_ne!(random.key, Cbc::with_random().key);
+        let ciphertext = random.encrypt(b"Hello, world").unwrap();
+        assert_eq!(random.decrypt(&ciphertext).unwrap(), b"Hello, world");
+        assert!(
+            cipher.decrypt(&ciphertext).is_err()
+                || cipher.decrypt(&ciphertext).unwrap() != b"Hello, world"
+        );
+    }
+
+    #[test]
+    fn test_seeded_ciphers() {
+        // FIPS-197 appendix C.1, the first block does not depend on the padding
+        let key: [u8; 16] = core::array::from_fn(|i| i as u8);
+        let plaintext: [u8; 16] = core::array::from_fn(|i| (i * 0x11) as u8);
+        let expected = [
+            0x69, 0xc4, 0xe0, 0xd8, 0x6a, 0x7b, 0x04, 0x30, 0xd8, 0xcd, 0xb7, 0x80, 0x70, 0xb4,
+            0xc5, 0x5a,
+        ];
+        let ecb = AesEcb::new(&key).unwrap().encrypt(&plaintext).unwrap();
+        let cbc = Cbc::new(key, [0; 16]).encrypt(&plaintext).unwrap();
+        assert_eq!(ecb[..16], expected);
+        assert_eq!(cbc[..16], expected);
+
+        let message = b"Hello, world";
+        assert_eq!(
+            AesEcb::from_seed(42).unwrap().encrypt(message).unwrap(),
+            AesEcb::from_seed(42).unwrap().encrypt(message).unwrap()
+        );
+        assert_ne!(
+            AesEcb::from_seed(42).unwrap().encrypt(message).unwrap(),
+            AesEcb::from_seed(43).unwrap().encrypt(message).unwrap()
+        );
+
+        let cbc = Cbc::from_seed(42);
+        assert_eq!(cbc.key, Cbc::from_seed(42).key);
+        assert_eq!(cbc.iv, Cbc::from_seed(42).iv);
+        assert_ne!(cbc.key, Cbc::from_seed(43).key);
+        let ciphertext = cbc.encrypt(message).unwrap();
+        assert_eq!(ciphertext, Cbc::from_seed(42).encrypt(message).unwrap());
+        assert_eq!(Cbc::from_seed(42).decrypt(&ciphertext).unwrap(), message);
+    }
+
+    #[test]
+    fn test_decrypt_cbc_lenient() {
+        let key = b"YELLOW SUBMARINE";
+        let iv = b"ivIVivIVivIVivIV";
+        let plaintext = b"Two full blocks of plaintext, and a few bytes";
+        let ciphertext = encrypt_cbc(plaintext, iv, key).unwrap();
+        assert_eq!(
+            decrypt_cbc_lenient(&ciphertext, iv, key).unwrap(),
+            plaintext
+        );
+
+        // Corrupt the last block, the padding is garbage
+        let mut corrupted = ciphertext.clone();
+        corrupted[40] ^= 0xff;
+        let error = decrypt_cbc(&corrupted, iv, key).unwrap_err();
+        assert!(error.is::<InvalidPadding>());
+        let decrypted = decrypt_cbc_lenient(&corrupted, iv, key).unwrap();
+        assert_eq!(decrypted.len(), 48);
+        assert_eq!(decrypted[..32], plaintext[..32]);
+        assert_ne!(decrypted[32..45], plaintext[32..]);
+
+        let error = decrypt_cbc_lenient(&corrupted[..47], iv, key).unwrap_err();
+        assert!(error.is::<InvalidCiphertext>());
+    }
+
+    #[test]
+    fn test_cbc_padding_is_valid() {
+        let key = b"YELLOW SUBMARINE";
+        let iv = b"ivIVivIVivIVivIV";
+        let ciphertext =
+            encrypt_cbc(b"Two full blocks of plaintext, and a few bytes", iv, key).unwrap();
+        assert!(cbc_padding_is_valid(&ciphertext, iv, key));
+
+        // Flipping a bit of the previous block changes the last padding byte from 3 to 2
+        let mut flipped = ciphertext.clone();
+        flipped[31] ^= 0x01;
+        assert!(!cbc_padding_is_valid(&flipped, iv, key));
+
+        assert!(!cbc_padding_is_valid(&ciphertext[..47], iv, key));
+        assert!(!cbc_padding_is_valid(b"", iv, key));
+    }
+
+    #[test]
+    fn test_cbc_padding() {
+        let iv = b"ivIVivIVivIVivIV";
+        let key = b"AZERTYUIOPASDFGH";
+        for padding in [Padding::Pkcs7, Padding::ZeroPad, Padding::AnsiX923] {
+            for plaintext in [
+                b"0".to_vec(),
+                b"YELLOW SUBMARINE".to_vec(),
+                b"banana banana banana".to_vec(),
+            ] {
+                let ciphertext = encrypt_cbc_with_padding(&plaintext, iv, key, padding).unwrap();
+                assert_eq!(ciphertext.len() % 16, 0);
+                assert_eq!(
+                    decrypt_cbc_with_padding(&ciphertext, iv, key, padding).unwrap(),
+                    plaintext
+                );
+            }
+        }
+
+        let plaintext = b"YELLOW SUBMARINEYELLOW SUBMARINE";
+        let ciphertext = encrypt_cbc_with_padding(plaintext, iv, key, Padding::NoPadding).unwrap();
+        assert_eq!(ciphertext.len(), 32);
+        assert_eq!(
+            decrypt_cbc_with_padding(&ciphertext, iv, key, Padding::NoPadding).unwrap(),
+            plaintext
+        );
+        assert!(encrypt_cbc_with_padding(b"banana", iv, key, Padding::NoPadding).is_err());
+
+        // The trailing zeros of the plaintext are lost with zero padding
+        let ciphertext =
+            encrypt_cbc_with_padding(b"banana\0\0", iv, key, Padding::ZeroPad).unwrap();
+        assert_eq!(
+            decrypt_cbc_with_padding(&ciphertext, iv, key, Padding::ZeroPad).unwrap(),
+            b"banana"
+        );
+
+        // PKCS#7 padding is checked when decrypting
+        let ciphertext = encrypt_cbc_with_padding(b"banana", iv, key, Padding::ZeroPad).unwrap();
+        assert!(decrypt_cbc(&ciphertext, iv, key).is_err());
+    }
+
+    #[test]
+    fn test_cbc_mac() {
+        let key = b"YELLOW SUBMARINE";
+        let iv = [0; 16];
+        let message = b"from=alice&to=bob&amount=0000010";
+
+        let mac = cbc_mac(message, key, &iv).unwrap();
+        let ciphertext = encrypt_cbc_with_padding(message, &iv, key, Padding::NoPadding).unwrap();
+        assert_eq!(mac, ciphertext[16..]);
+
+        assert_ne!(
+            mac,
+            cbc_mac(b"from=alice&to=bob&amount=0000011", key, &iv).unwrap()
+        );
+        assert!(cbc_mac(b"from=alice", key, &iv).is_err());
+        assert!(cbc_mac(b"", key, &iv).is_err());
+    }
+
+    #[test]
+    fn test_cbc_mac_iv_forgery() {
+        let key = b"YELLOW SUBMARINE";
+        let iv = *b"ivIVivIVivIVivIV";
+        let message = b"from=alice&to=bo&amount=00000010";
+        let forged = b"from=alice&to=ev&amount=00000010";
+
+        let forged_iv = forge_cbc_mac_iv(
+            &iv,
+            message[..16].try_into().unwrap(),
+            forged[..16].try_into().unwrap(),
+        );
+        assert_ne!(forged_iv, iv);
+        assert_eq!(
+            cbc_mac(message, key, &iv).unwrap(),
+            cbc_mac(forged, key, &forged_iv).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_encrypt_many_fixed_nonce() {
+        let plaintexts: [&[u8]; 3] = [
+            b"I have met them at close of day",
+            b"Coming with vivid faces",
+            b"From counter or desk among grey eighteenth-century houses.",
+        ];
+        let key = b"YELLOW SUBMARINE";
+        let ciphertexts = encrypt_many_fixed_nonce(&plaintexts, key, 42).unwrap();
+
+        assert_eq!(
+            ciphertexts[2],
+            ctr(plaintexts[2], key, 42, CounterEndian::Little).unwrap()
+        );
+        for (c1, p1) in ciphertexts.iter().zip(plaintexts) {
+            assert_eq!(c1.len(), p1.len());
+            for (c2, p2) in ciphertexts.iter().zip(plaintexts) {
+                // Same keystream everywhere: it cancels out
+                for i in 0..c1.len().min(c2.len()) {
+                    assert_eq!(c1[i] ^ c2[i], p1[i] ^ p2[i]);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_ctr_keystream() {
+        let key = b"YELLOW SUBMARINE";
+        let keystream = ctr_keystream(key, 0, CounterEndian::Little, 0, 64).unwrap();
+        assert_eq!(keystream.len(), 64);
+        assert_eq!(
+            ctr(&[0; 64], key, 0, CounterEndian::Little).unwrap(),
+            keystream
+        );
+
+        for (offset, len) in [
+            (20, 10),
+            (0, 10),
+            (16, 16),
+            (15, 2),
+            (30, 34),
+            (63, 1),
+            (5, 0),
+        ] {
+            assert_eq!(
+                ctr_keystream(key, 0, CounterEndian::Little, offset, len).unwrap(),
+                keystream[offset..offset + len]
+            );
+        }
+    }
+
+    #[test]
+    fn test_cbc_with_password() {
+        let salt = b"0123456789abcdef";
+        let plaintext = b"Attack at dawn";
+
+        let data = encrypt_cbc_with_password(plaintext, b"hunter2", salt).unwrap();
+        assert_eq!(data.len(), 16 + 16 + 16);
+        assert_eq!(&data[..16], salt);
+        assert_eq!(
+            decrypt_cbc_with_password(&data, b"hunter2").unwrap(),
+            plaintext
+        );
+        assert_ne!(
+            decrypt_cbc_with_password(&data, b"hunter3").ok(),
+            Some(plaintext.to_vec())
+        );
+        assert_ne!(
+            data,
+            encrypt_cbc_with_password(plaintext, b"hunter2", salt).unwrap()
+        );
+        assert!(decrypt_cbc_with_password(&data[..31], b"hunter2").is_err());
+    }
+
+    #[test]
+    fn test_cbc_prepend_iv() {
+        let key = b"YELLOW SUBMARINE";
+        let plaintext = b"Attack at dawn";
+
+        let data = encrypt_cbc_prepend_iv(plaintext, key).unwrap();
+        assert_eq!(data.len(), 16 + 16);
+        assert_eq!(decrypt_cbc_prepend_iv(&data, key).unwrap(), plaintext);
+        assert_eq!(
+            decrypt_cbc(&data[16..], data[..16].try_into().unwrap(), key).unwrap(),
+            plaintext
+        );
+
+        let other = encrypt_cbc_prepend_iv(plaintext, key).unwrap();
+        assert_ne!(data[..16], other[..16]);
+        assert_ne!(data[16..], other[16..]);
+        assert_eq!(decrypt_cbc_prepend_iv(&other, key).unwrap(), plaintext);
+
+        let error = decrypt_cbc_prepend_iv(&data[..31], key).unwrap_err();
+        assert!(error.is::<InvalidCiphertext>());
+    }
+
+    #[test]
+    fn test_encrypt_then_mac() {
+        let enc_key = b"YELLOW SUBMARINE";
+        let mac_key = b"MELLOW TANGERINE";
+        let plaintext = b"Attack at dawn";
+
+        let data = encrypt_then_mac(plaintext, enc_key, mac_key).unwrap();
+        assert_eq!(data.len(), 16 + 16 + MAC_LEN);
+        assert_eq!(
+            decrypt_and_verify(&data, enc_key, mac_key).unwrap(),
+            plaintext
+        );
+        assert_ne!(data, encrypt_then_mac(plaintext, enc_key, mac_key).unwrap());
+
+        for position in [0, 16, 31, data.len() - 1] {
+            let mut tampered = data.clone();
+            tampered[position] ^= 1;
+            let error = decrypt_and_verify(&tampered, enc_key, mac_key).unwrap_err();
+            assert!(error.is::<AuthenticationFailed>());
+        }
+        let error = decrypt_and_verify(&data, enc_key, b"wrong key").unwrap_err();
+        assert!(error.is::<AuthenticationFailed>());
+
+        let error = decrypt_and_verify(&data[..35], enc_key, mac_key).unwrap_err();
+        assert!(error.is::<InvalidCiphertext>());
+    }
+
+    #[test]
+    fn test_stream_cipher() {
+        let key = b"YELLOW SUBMARINE";
+        let ciphers: Vec<Box<dyn StreamCipher>> = vec![
+            Box::new(AesCtr::new(key, 0, CounterEndian::Little).unwrap()),
+            Box::new(AesCtr::new(key, 42, CounterEndian::Big).unwrap()),
+            Box::new(AesOfb::new(key, b"ivIVivIVivIVivIV").unwrap()),
+        ];
+        for cipher in ciphers {
+            for data in [
+                b"".to_vec(),
+                b"0".to_vec(),
+                b"YELLOW SUBMARINE".to_vec(),
+                b"banana banana banana".to_vec(),
+            ] {
+                let encrypted = cipher.apply(&data);
+                assert_eq!(encrypted.len(), data.len());
+                assert_eq!(cipher.apply(&encrypted), data);
+            }
+            assert_ne!(
+                cipher.apply(b"banana banana banana"),
+                b"banana banana banana"
+            );
+        }
+
+        let ctr_cipher = AesCtr::new(key, 7, CounterEndian::Little).unwrap();
+        assert_eq!(
+            ctr_cipher.apply(b"banana banana banana"),
+            ctr(b"banana banana banana", key, 7, CounterEndian::Little).unwrap()
+        );
+
+        // The second OFB keystream block is the encryption of the first one
+        let ofb_cipher = AesOfb::new(key, &[0; 16]).unwrap();
+        let keystream = ofb_cipher.keystream(32);
+        assert_eq!(keystream[..16], encrypt_ecb([0; 16], key).unwrap()[..16]);
+        assert_eq!(
+            keystream[16..],
+            encrypt_ecb(&keystream[..16], key).unwrap()[..16]
+        );
+    }
+
     #[test]
     fn test_oracle() {
         let iv = b"ivIVivIVivIVivIV";
         let key = b"AZERTYUIOPASDFGH";
 
         assert_eq!(
-            oracle(|data| encrypt_cbc(&data, iv, key).unwrap()),
+            oracle(|data| encrypt_cbc(data, iv, key).unwrap()),
             Protocol::Cbc
         );
         assert_eq!(
-            oracle(|data| encrypt_ecb(&data, key).unwrap()),
+            oracle(|data| encrypt_ecb(data, key).unwrap()),
             Protocol::Ecb
         );
     }
 
+    #[test]
+    fn test_oracle_with_block_size() {
+        let key = b"8bytekey";
+        // Toy 8-byte block ciphers, with a prefix so that the data is not aligned
+        let toy_ecb = |data: &[u8]| -> Vec<u8> {
+            let padded = Padding::Pkcs7
+                .pad(&[b"pre", data].concat(), BlockSize::new(8).unwrap())
+                .unwrap();
+            padded
+                .iter()
+                .enumerate()
+                .map(|(i, b)| b.rotate_left(3) ^ key[i % 8])
+                .collect()
+        };
+        let toy_cbc = |data: &[u8]| -> Vec<u8> {
+            let mut encrypted = toy_ecb(data);
+            for i in 8..encrypted.len() {
+                encrypted[i] = encrypted[i].wrapping_add(encrypted[i - 8]);
+            }
+            encrypted
+        };
+
+        assert_eq!(oracle_with_block_size(toy_ecb, 8), Protocol::Ecb);
+        assert_eq!(oracle_with_block_size(toy_cbc, 8), Protocol::Cbc);
+
+        let key = b"AZERTYUIOPASDFGH";
+        assert_eq!(
+            oracle_with_block_size(|data| encrypt_ecb(data, key).unwrap(), 16),
+            Protocol::Ecb
+        );
+    }
+
+    #[test]
+    fn test_oracle_detailed() {
+        let iv = b"ivIVivIVivIVivIV";
+        let key = b"AZERTYUIOPASDFGH";
+
+        assert_eq!(
+            oracle_detailed(|data| encrypt_cbc(data, iv, key).unwrap()),
+            (Protocol::Cbc, 0.0)
+        );
+        assert_eq!(
+            oracle_detailed(|data| encrypt_ecb(data, key).unwrap()),
+            (Protocol::Ecb, 1.0)
+        );
+
+        for _ in 0..10 {
+            let mut real_protocol = Protocol::Cbc;
+            let (protocol, confidence) = oracle_detailed(|x| {
+                let res = unknown_encryption(x).unwrap();
+                real_protocol = res.0;
+                res.1
+            });
+            assert_eq!(protocol, real_protocol);
+            assert!(confidence == 0.0 || confidence == 1.0);
+        }
+    }
+
+    #[test]
+    fn test_unknown_encryption_with_rng() {
+        use rand::rngs::StdRng;
+
+        for (seed, expected_protocol) in [(0, Protocol::Cbc), (1, Protocol::Ecb)] {
+            let data1 =
+                unknown_encryption_with_rng(b"hello", &mut StdRng::seed_from_u64(seed)).unwrap();
+            let data2 =
+                unknown_encryption_with_rng(b"hello", &mut StdRng::seed_from_u64(seed)).unwrap();
+            assert_eq!(data1.0, expected_protocol);
+            assert_eq!(data1, data2);
+        }
+    }
+
     #[test]
     fn test_unknown_encryption() {
         for sample_data in [b"".to_vec(), b"hello".to_vec(), b"c".repeat(500)] {
@@ -0,0 +1,324 @@
+use std::{collections::HashSet, fmt};
+
+use crate::aes::encrypt_ecb;
+use crate::block::BlockSize;
+use crate::decrypt::max_by_score;
+use crate::BoxResult;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum CipherGuess {
+    Ecb,
+    Cbc,
+    Stream,
+}
+
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CiphertextReport {
+    pub length: usize,
+    pub block_aligned: bool,
+    pub repeated_blocks: usize,
+    pub guess: CipherGuess,
+}
+
+impl fmt::Display for CiphertextReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "length: {}, block aligned: {}, repeated blocks: {}, guess: {:?}",
+            self.length, self.block_aligned, self.repeated_blocks, self.guess
+        )
+    }
+}
+
+/// Number of blocks that are an exact copy of a previous block. A trailing partial block is
+/// ignored.
+pub fn count_repeated_blocks(data: &[u8], block_size: usize) -> usize {
+    let mut seen = HashSet::new();
+    data.chunks_exact(block_size)
+        .filter(|block| !seen.insert(*block))
+        .count()
+}
+
+/// Number of occurrences of each byte value
+pub fn byte_histogram(data: &[u8]) -> [usize; 256] {
+    let mut histogram = [0; 256];
+    for &b in data {
+        histogram[b as usize] += 1;
+    }
+    histogram
+}
+
+/// Chi-square statistic of the byte values against a uniform distribution, 0 for empty data.
+/// Encrypted or compressed data stays around 255 (the degrees of freedom), while text is way
+/// above since it only uses a few byte values.
+pub fn chi_square_uniform(data: &[u8]) -> f64 {
+    if data.is_empty() {
+        return 0.0;
+    }
+    let expected = data.len() as f64 / 256.0;
+    byte_histogram(data)
+        .iter()
+        .map(|&count| (count as f64 - expected).powi(2) / expected)
+        .sum()
+}
+
+/// Shannon entropy of the byte values, in bits per byte: 0 for constant data, close to 8 for
+/// encrypted or compressed data, around 4.5 for English text.
+pub fn shannon_entropy(data: &[u8]) -> f64 {
+    let len = data.len() as f64;
+    byte_histogram(data)
+        .iter()
+        .filter(|&&count| count > 0)
+        .map(|&count| {
+            let p = count as f64 / len;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+/// Encrypts the data with ECB and counts the repeated ciphertext blocks, to show how much of the
+/// structure of the plaintext leaks through: every repeated plaintext block is still repeated.
+pub fn ecb_encrypt_showcase(data: &[u8], key: &[u8; 16]) -> BoxResult<(Vec<u8>, usize)> {
+    let ciphertext = encrypt_ecb(data, key)?;
+    let repeated_blocks = count_repeated_blocks(&ciphertext, BlockSize::AES_BLK_SZ_USIZE);
+    Ok((ciphertext, repeated_blocks))
+}
+
+/// Indices of the lines with at least `min_repeats` repeated 16-byte blocks, the most repeated
+/// first (ties keep the order of the lines).
+pub fn find_ecb_lines(lines: &[Vec<u8>], min_repeats: usize) -> Vec<usize> {
+    let mut found: Vec<(usize, usize)> = lines
+        .iter()
+        .map(|line| count_repeated_blocks(line, BlockSize::AES_BLK_SZ_USIZE))
+        .enumerate()
+        .filter(|(_, repeats)| *repeats >= min_repeats)
+        .collect();
+    found.sort_by_key(|&(_, repeats)| std::cmp::Reverse(repeats));
+    found.into_iter().map(|(index, _)| index).collect()
+}
+
+/// Index of the line most likely encrypted with ECB, if any line has a repeated block, with its
+/// confidence: the ratio of repeated blocks, so that lines of different lengths can be compared.
+/// Ties keep the first line.
+pub fn find_ecb_line(lines: &[Vec<u8>]) -> Option<(usize, f64)> {
+    let block_size = BlockSize::AES_BLK_SZ_USIZE;
+    let candidates = lines.iter().enumerate().filter_map(|(index, line)| {
+        let repeats = count_repeated_blocks(line, block_size);
+        // There is at least one block when there is a repeat
+        (repeats > 0).then(|| (index, repeats as f64 / (line.len() / block_size) as f64))
+    });
+    max_by_score(candidates, |(_, ratio)| *ratio as f32)
+}
+
+/// True when both ciphertexts have a full first block and these blocks are identical
+pub fn first_blocks_equal(c1: &[u8], c2: &[u8], block_size: usize) -> bool {
+    c1.len() >= block_size && c2.len() >= block_size && c1[..block_size] == c2[..block_size]
+}
+
+/// Pairs of CBC ciphertexts (by index) with the same first block. With a random IV this is
+/// extremely unlikely, so it means that the IV was reused and that the plaintexts share their
+/// first block.
+pub fn detect_possible_iv_reuse(ciphertexts: &[Vec<u8>]) -> Vec<(usize, usize)> {
+    let mut pairs = vec![];
+    for (i, c1) in ciphertexts.iter().enumerate() {
+        for (j, c2) in ciphertexts.iter().enumerate().skip(i + 1) {
+            if first_blocks_equal(c1, c2, BlockSize::AES_BLK_SZ_USIZE) {
+                pairs.push((i, j));
+            }
+        }
+    }
+    pairs
+}
+
+/// Quick triage of an unknown ciphertext. A length that is not a multiple of the block size
+/// points to a stream cipher, and repeated blocks are the signature of ECB: the same plaintext
+/// block always gives the same ciphertext block.
+pub fn analyze(ciphertext: &[u8]) -> CiphertextReport {
+    let block_size = BlockSize::AES_BLK_SZ_USIZE;
+    let block_aligned = !ciphertext.is_empty() && ciphertext.len().is_multiple_of(block_size);
+    let repeated_blocks = count_repeated_blocks(ciphertext, block_size);
+
+    let guess = if !block_aligned {
+        CipherGuess::Stream
+    } else if repeated_blocks > 0 {
+        CipherGuess::Ecb
+    } else {
+        CipherGuess::Cbc
+    };
+
+    CiphertextReport {
+        length: ciphertext.len(),
+        block_aligned,
+        repeated_blocks,
+        guess,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::aes::{encrypt_cbc, encrypt_ecb};
+    use crate::analysis::*;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    #[test]
+    fn test_byte_histogram() {
+        let histogram = byte_histogram(b"hello");
+        assert_eq!(histogram[b'l' as usize], 2);
+        assert_eq!(histogram[b'h' as usize], 1);
+        assert_eq!(histogram.iter().sum::<usize>(), 5);
+        assert_eq!(byte_histogram(&[]), [0; 256]);
+    }
+
+    #[test]
+    fn test_chi_square_uniform() {
+        assert_eq!(chi_square_uniform(&[]), 0.0);
+        let all_bytes: Vec<u8> = (0..=255).collect();
+        assert_eq!(chi_square_uniform(&all_bytes.repeat(4)), 0.0);
+
+        let text = b"Now that the party is jumping, with the bass kicked in and the Vega's are \
+            pumpin', quick to the point, to the point, no faking, cooking MC's like a pound of \
+            bacon. Burning 'em, if you ain't quick and nimble, I go crazy when I hear a cymbal."
+            .repeat(10);
+        let mut random_data = vec![0u8; text.len()];
+        StdRng::seed_from_u64(0).fill(&mut random_data[..]);
+        let ciphertext = encrypt_cbc(&text, &[0; 16], b"YELLOW SUBMARINE").unwrap();
+
+        let text_score = chi_square_uniform(&text);
+        assert!(text_score > 10.0 * chi_square_uniform(&random_data));
+        assert!(text_score > 10.0 * chi_square_uniform(&ciphertext));
+        assert!(chi_square_uniform(&random_data) < 400.0);
+    }
+
+    #[test]
+    fn test_shannon_entropy() {
+        assert_eq!(shannon_entropy(&[]), 0.0);
+        assert_eq!(shannon_entropy(&[42; 1000]), 0.0);
+        assert_eq!(shannon_entropy(&[0, 1, 2, 3].repeat(10)), 2.0);
+
+        let mut random_data = [0u8; 64 * 1024];
+        StdRng::seed_from_u64(0).fill(&mut random_data[..]);
+        let random_entropy = shannon_entropy(&random_data);
+        assert!(random_entropy > 7.99 && random_entropy <= 8.0);
+
+        let text = b"Now that the party is jumping, with the bass kicked in and the Vega's are \
+            pumpin', quick to the point, to the point, no faking, cooking MC's like a pound of \
+            bacon. Burning 'em, if you ain't quick and nimble, I go crazy when I hear a cymbal.";
+        let text_entropy = shannon_entropy(text);
+        assert!(text_entropy > 3.5 && text_entropy < 5.0, "{}", text_entropy);
+    }
+
+    #[test]
+    fn test_ecb_encrypt_showcase() {
+        let key = b"YELLOW SUBMARINE";
+
+        // 64 identical blocks, plus the padding block
+        let (ciphertext, repeated) = ecb_encrypt_showcase(&[0xaa; 64 * 16], key).unwrap();
+        assert_eq!(ciphertext, encrypt_ecb([0xaa; 64 * 16], key).unwrap());
+        assert_eq!(repeated, 63);
+
+        // Two alternating "pixel" rows
+        let image = [[1u8; 16], [2u8; 16]].repeat(10).concat();
+        assert_eq!(ecb_encrypt_showcase(&image, key).unwrap().1, 18);
+
+        let mut random_data = [0u8; 64 * 16];
+        StdRng::seed_from_u64(0).fill(&mut random_data[..]);
+        assert_eq!(ecb_encrypt_showcase(&random_data, key).unwrap().1, 0);
+    }
+
+    #[test]
+    fn test_count_repeated_blocks() {
+        assert_eq!(count_repeated_blocks(&[], 2), 0);
+        assert_eq!(count_repeated_blocks(&[1, 2, 3, 4], 2), 0);
+        assert_eq!(count_repeated_blocks(&[1, 2, 1, 2, 1, 2, 3, 4], 2), 2);
+        assert_eq!(count_repeated_blocks(&[1, 2, 3, 4, 1, 2, 3, 4, 1], 4), 1);
+    }
+
+    #[test]
+    fn test_find_ecb_lines() {
+        let block = b"0123456789abcdef";
+        let lines = vec![
+            (0..64).collect::<Vec<u8>>(),
+            [block.repeat(2), b"0123456789ABCDEF".to_vec()].concat(),
+            (64..128).collect(),
+            block.repeat(4),
+            vec![],
+        ];
+
+        assert_eq!(find_ecb_lines(&lines, 1), vec![3, 1]);
+        assert_eq!(find_ecb_lines(&lines, 2), vec![3]);
+        assert!(find_ecb_lines(&lines, 4).is_empty());
+        assert_eq!(find_ecb_line(&lines), Some((3, 0.75)));
+        assert_eq!(find_ecb_line(&lines[..1]), None);
+    }
+
+    #[test]
+    fn test_find_ecb_line_confidence() {
+        let key = b"YELLOW SUBMARINE";
+        let block = b"0123456789abcdef";
+        // 64 random-looking blocks with a coincidental duplicate
+        let mut long_line = encrypt_cbc(&[0; 64 * 16], &[0; 16], key).unwrap();
+        long_line.copy_within(0..16, 32 * 16);
+        let short_line = encrypt_ecb(block.repeat(2), key).unwrap();
+
+        let lines = vec![long_line, short_line];
+        assert_eq!(count_repeated_blocks(&lines[0], 16), 1);
+        assert_eq!(count_repeated_blocks(&lines[1], 16), 1);
+        // Same number of repeats, so the raw count keeps the first one
+        assert_eq!(find_ecb_lines(&lines, 1), vec![0, 1]);
+        // 1 repeat out of 3 blocks, the padding block included
+        assert_eq!(find_ecb_line(&lines), Some((1, 1.0 / 3.0)));
+
+        // Ties keep the first line
+        let lines = vec![block.repeat(2), block.repeat(2)];
+        assert_eq!(find_ecb_line(&lines), Some((0, 0.5)));
+    }
+
+    #[test]
+    fn test_detect_possible_iv_reuse() {
+        let key = b"YELLOW SUBMARINE";
+        let iv = [7; 16];
+        let ciphertexts = vec![
+            encrypt_cbc(b"comment1=cooking%20MCs;userdata=foo", &iv, key).unwrap(),
+            encrypt_cbc(b"comment1=cooking%20MCs;userdata=bar", &[8; 16], key).unwrap(),
+            encrypt_cbc(b"comment1=cooking%20MCs;userdata=baz", &iv, key).unwrap(),
+            encrypt_cbc(b"something else", &iv, key).unwrap(),
+            vec![],
+        ];
+
+        assert!(first_blocks_equal(&ciphertexts[0], &ciphertexts[2], 16));
+        assert!(!first_blocks_equal(&ciphertexts[0], &ciphertexts[1], 16));
+        assert!(!first_blocks_equal(&ciphertexts[4], &ciphertexts[4], 16));
+        assert_eq!(detect_possible_iv_reuse(&ciphertexts), vec![(0, 2)]);
+    }
+
+    #[test]
+    fn test_analyze() {
+        let key = b"YELLOW SUBMARINE";
+        let plaintext = [b"0123456789abcdef".repeat(3), b"something else".to_vec()].concat();
+
+        let report = analyze(&encrypt_ecb(&plaintext, key).unwrap());
+        assert_eq!(
+            report,
+            CiphertextReport {
+                length: 64,
+                block_aligned: true,
+                repeated_blocks: 2,
+                guess: CipherGuess::Ecb
+            }
+        );
+        assert_eq!(
+            report.to_string(),
+            "length: 64, block aligned: true, repeated blocks: 2, guess: Ecb"
+        );
+
+        let report = analyze(&encrypt_cbc(&plaintext, &[0; 16], key).unwrap());
+        assert_eq!(report.repeated_blocks, 0);
+        assert_eq!(report.guess, CipherGuess::Cbc);
+
+        let report = analyze(&plaintext[..50]);
+        assert!(!report.block_aligned);
+        assert_eq!(report.guess, CipherGuess::Stream);
+    }
+}
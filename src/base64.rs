@@ -1,39 +1,65 @@
-use std::{error::Error, fmt, fs::File, io::Read};
+use alloc::{string::String, vec, vec::Vec};
+use core::{error::Error, fmt, iter::Enumerate};
+#[cfg(feature = "std")]
+use std::{
+    env,
+    fs::File,
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+#[cfg(feature = "std")]
+use crate::BoxResult;
 
 // https://datatracker.ietf.org/doc/html/rfc4648#section-4
-fn to_base64_char(b: u8) -> char {
-    match b {
-        0..=25 => (b + b'A') as char,
-        26..=51 => (b - 26 + b'a') as char,
-        52..=61 => (b - 52 + b'0') as char,
-        62 => '+',
-        63 => '/',
-        _ => panic!("Invalid base64 character"), // TODO: don't panic!
+// Single source of truth for both the encoding and the decoding
+const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+const INVALID_CHAR: u8 = 0xff;
+
+// Value of each ASCII character, INVALID_CHAR if it is not in the alphabet
+const DECODING_TABLE: [u8; 128] = {
+    let mut table = [INVALID_CHAR; 128];
+    let mut i = 0;
+    while i < ALPHABET.len() {
+        table[ALPHABET[i] as usize] = i as u8;
+        i += 1;
     }
+    table
+};
+
+// Only the 6 low bits are used, so that every value maps to a character
+fn to_base64_char(b: u8) -> char {
+    ALPHABET[(b & 0b111111) as usize] as char
 }
 
-#[derive(Debug)]
+/// `position` is the index of the offending character in the input string, counted
+/// in characters (not bytes), whitespace included.
+#[derive(Debug, PartialEq)]
 pub struct InvalidBase64Char {
     character: char,
+    position: usize,
 }
 
 impl fmt::Display for InvalidBase64Char {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Invalid Base64 data, char {} is invalid", self.character)
+        write!(
+            f,
+            "Invalid Base64 data, char {} at position {} is invalid",
+            self.character, self.position
+        )
     }
 }
 
 impl Error for InvalidBase64Char {}
 
-// https://datatracker.ietf.org/doc/html/rfc4648#section-4
-fn from_base64_char(c: char) -> Result<u8, InvalidBase64Char> {
-    match c {
-        'A'..='Z' => Ok(c as u8 - b'A'),
-        'a'..='z' => Ok(c as u8 - b'a' + 26),
-        '0'..='9' => Ok(c as u8 - b'0' + 52),
-        '+' => Ok(62),
-        '/' => Ok(63),
-        _ => Err(InvalidBase64Char { character: c }),
+fn from_base64_char(c: char, position: usize) -> Result<u8, InvalidBase64Char> {
+    match DECODING_TABLE.get(c as usize) {
+        Some(&value) if value != INVALID_CHAR => Ok(value),
+        _ => Err(InvalidBase64Char {
+            character: c,
+            position,
+        }),
     }
 }
 
@@ -73,53 +99,189 @@ pub fn to_base64(data: &[u8]) -> String {
     result
 }
 
+/// Same as `to_base64`, without the trailing `=`. `from_base64` decodes it as is.
+pub fn to_base64_unpadded(data: &[u8]) -> String {
+    let mut result = to_base64(data);
+    result.truncate(result.trim_end_matches('=').len());
+    result
+}
+
+/// Base64 encoding as ASCII bytes, for byte-oriented pipelines
+pub fn to_base64_bytes(data: &[u8]) -> Vec<u8> {
+    to_base64(data).into_bytes()
+}
+
 pub fn from_base64(data: &str) -> Result<Vec<u8>, InvalidBase64Char> {
-    let mut output = Vec::new();
-    let mut current_char = 0u8;
-    let mut state_size = 0;
-    for c in data.chars() {
-        if c.is_ascii_whitespace() {
-            continue;
+    decode_base64_chars(data.chars())
+}
+
+/// Same as `from_base64` for base64 data stored as bytes. The position reported in the error is
+/// the index of the byte.
+pub fn from_base64_bytes(data: &[u8]) -> Result<Vec<u8>, InvalidBase64Char> {
+    decode_base64_chars(data.iter().map(|&b| b as char))
+}
+
+/// Same as `from_base64`, also returning the number of `=` padding characters found in the input.
+pub fn from_base64_with_padding(data: &str) -> Result<(Vec<u8>, u8), InvalidBase64Char> {
+    decode_base64_chars_with_padding(data.chars())
+}
+
+/// Best-effort version of `from_base64`, for data with a few corrupt characters: each invalid
+/// character is decoded as if it was an `A` (zero bits). Also returns the positions of these
+/// characters, counted like in `InvalidBase64Char`.
+pub fn from_base64_lossy(data: &str) -> (Vec<u8>, Vec<usize>) {
+    let mut fixed_positions = vec![];
+    let chars = data.chars().enumerate().map(|(position, c)| {
+        if c.is_ascii_whitespace() || c == '=' || from_base64_char(c, position).is_ok() {
+            c
+        } else {
+            fixed_positions.push(position);
+            'A'
         }
-        if c == '=' {
-            // Flush the state if we haven't
-            if state_size > 4 {
-                output.push(current_char);
-                state_size = 0;
-            }
-            continue;
+    });
+    // Only valid characters are left
+    let output = decode_base64_chars(chars).unwrap();
+    (output, fixed_positions)
+}
+
+fn decode_base64_chars(chars: impl Iterator<Item = char>) -> Result<Vec<u8>, InvalidBase64Char> {
+    decode_base64_chars_with_padding(chars).map(|(output, _)| output)
+}
+
+fn decode_base64_chars_with_padding(
+    chars: impl Iterator<Item = char>,
+) -> Result<(Vec<u8>, u8), InvalidBase64Char> {
+    let mut decoder = Base64Decoder::new(chars);
+    let output = decoder.by_ref().collect::<Result<Vec<u8>, _>>()?;
+    Ok((output, decoder.padding))
+}
+
+/// Lazy version of `from_base64`, the bytes are decoded as the characters are read. The
+/// iterator stops after the first error.
+pub fn decode_base64_iter(
+    chars: impl Iterator<Item = char>,
+) -> impl Iterator<Item = Result<u8, InvalidBase64Char>> {
+    Base64Decoder::new(chars)
+}
+
+struct Base64Decoder<I: Iterator<Item = char>> {
+    chars: Enumerate<I>,
+    current_char: u8,
+    state_size: u8,
+    // Number of trailing `=` found so far, at most 2
+    padding: u8,
+    failed: bool,
+}
+
+impl<I: Iterator<Item = char>> Base64Decoder<I> {
+    fn new(chars: I) -> Base64Decoder<I> {
+        Base64Decoder {
+            chars: chars.enumerate(),
+            current_char: 0,
+            state_size: 0,
+            padding: 0,
+            failed: false,
         }
-        let new_char = from_base64_char(c)?;
-        match state_size {
-            0 => {
-                current_char = new_char << 2;
-                state_size = 6;
-            }
-            6 => {
-                output.push(current_char | (new_char & 0b110000) >> 4);
-                current_char = (new_char & 0b1111) << 4;
-                state_size = 4;
+    }
+}
+
+impl<I: Iterator<Item = char>> Iterator for Base64Decoder<I> {
+    type Item = Result<u8, InvalidBase64Char>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        for (position, c) in self.chars.by_ref() {
+            if c.is_ascii_whitespace() {
+                continue;
             }
-            4 => {
-                output.push(current_char | (new_char >> 2));
-                current_char = (0b11 & new_char) << 6;
-                state_size = 2;
+            if c == '=' {
+                self.padding = (self.padding + 1).min(2);
+                // Flush the state if we haven't
+                if self.state_size > 4 {
+                    self.state_size = 0;
+                    return Some(Ok(self.current_char));
+                }
+                continue;
             }
-            2 => {
-                output.push(current_char | new_char);
-                current_char = 0;
-                state_size = 0;
+            let new_char = match from_base64_char(c, position) {
+                Ok(new_char) => new_char,
+                Err(e) => {
+                    self.failed = true;
+                    return Some(Err(e));
+                }
+            };
+            // Only the `=` at the end of the data are padding
+            self.padding = 0;
+            let output = self.current_char;
+            match self.state_size {
+                0 => {
+                    self.current_char = new_char << 2;
+                    self.state_size = 6;
+                }
+                6 => {
+                    self.current_char = (new_char & 0b1111) << 4;
+                    self.state_size = 4;
+                    return Some(Ok(output | (new_char & 0b110000) >> 4));
+                }
+                4 => {
+                    self.current_char = (0b11 & new_char) << 6;
+                    self.state_size = 2;
+                    return Some(Ok(output | (new_char >> 2)));
+                }
+                2 => {
+                    self.current_char = 0;
+                    self.state_size = 0;
+                    return Some(Ok(output | new_char));
+                }
+                _ => panic!("Impossible!"),
             }
-            _ => panic!("Impossible!"),
         }
+        None
+    }
+}
+
+/// Serializes `Vec<u8>` fields as base64 strings, with `#[serde(with = "crate::base64::serde")]`
+#[cfg(feature = "serde")]
+pub mod serde {
+    use alloc::{string::String, vec::Vec};
+    use serde::{de, Deserialize, Deserializer, Serializer};
+
+    use super::{from_base64, to_base64};
+
+    pub fn serialize<S: Serializer>(data: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&to_base64(data))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let base64_data = String::deserialize(deserializer)?;
+        from_base64(&base64_data).map_err(de::Error::custom)
     }
-    Ok(output)
 }
 
-pub fn load_base64_file(file_id: &str) -> Result<Vec<u8>, Box<dyn std::error::Error + 'static>> {
+#[cfg(feature = "std")]
+/// Environment variable overriding the location of the challenge data files
+pub const DATA_DIR_ENV: &str = "CRYPTOPALS_DATA_DIR";
+
+#[cfg(feature = "std")]
+/// Directory holding the challenge data files: `$CRYPTOPALS_DATA_DIR` if set, `data/` otherwise.
+pub fn data_dir() -> PathBuf {
+    env::var_os(DATA_DIR_ENV)
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("data"))
+}
+
+#[cfg(feature = "std")]
+pub fn load_base64_file(file_id: &str) -> BoxResult<Vec<u8>> {
+    load_base64_file_from(data_dir(), file_id)
+}
+
+#[cfg(feature = "std")]
+pub fn load_base64_file_from(data_dir: impl AsRef<Path>, file_id: &str) -> BoxResult<Vec<u8>> {
     let mut base64_data = String::new();
-    let file_name = format!("data/{}.txt", file_id);
-    File::open(file_name.clone()).and_then(|mut fd| fd.read_to_string(&mut base64_data))?;
+    let file_name = data_dir.as_ref().join(format!("{}.txt", file_id));
+    File::open(file_name).and_then(|mut fd| fd.read_to_string(&mut base64_data))?;
     Ok(from_base64(&base64_data)?)
 }
 
@@ -146,6 +308,26 @@ mod tests {
         (&[0xfa], "+g=="),
     ];
 
+    #[test]
+    fn test_base64_char() {
+        for index in 0..64u8 {
+            let c = to_base64_char(index);
+            assert!(c.is_ascii_alphanumeric() || c == '+' || c == '/');
+            assert_eq!(from_base64_char(c, 0), Ok(index));
+            assert_eq!(to_base64_char(index | 0b11000000), c);
+        }
+
+        let valid: Vec<u8> = (0..128u8)
+            .filter(|&c| from_base64_char(c as char, 0).is_ok())
+            .collect();
+        let mut alphabet = ALPHABET.to_vec();
+        alphabet.sort();
+        assert_eq!(valid, alphabet);
+        assert!(from_base64_char('=', 0).is_err());
+        assert!(from_base64_char('é', 0).is_err());
+        assert!(from_base64_char('\u{141}', 0).is_err());
+    }
+
     #[test]
     fn test_tobase64() {
         for (data, b64_data) in BASE64_VALUES {
@@ -168,6 +350,145 @@ mod tests {
         assert!(from_base64("Je & pense").is_err());
     }
 
+    #[test]
+    fn test_base64_unpadded() {
+        for (data, b64_data) in BASE64_VALUES {
+            assert_eq!(to_base64_unpadded(data), b64_data.trim_end_matches('='));
+        }
+
+        let data: Vec<u8> = (0..10).collect();
+        for len in 0..data.len() {
+            assert_eq!(
+                from_base64(&to_base64_unpadded(&data[..len])).unwrap(),
+                &data[..len]
+            );
+        }
+    }
+
+    #[test]
+    fn test_from_base64_lossy() {
+        // "SGVsbG8sIHdvcmxkIQ==" with the 'b' replaced
+        let (data, fixed) = from_base64_lossy("SGVs!G8sIHdvcmxkIQ==");
+        assert_eq!(fixed, vec![4]);
+        assert_eq!(data.len(), b"Hello, world!".len());
+        assert_eq!(data[..3], *b"Hel");
+        assert_eq!(data[5..], *b", world!");
+        assert!(from_base64("SGVs!G8sIHdvcmxkIQ==").is_err());
+
+        let (data, fixed) = from_base64_lossy("SGVs\nbG8?IHdvcm*kIQ==");
+        assert_eq!(fixed, vec![8, 15]);
+        assert_eq!(data.len(), 13);
+
+        for (data, b64_data) in BASE64_VALUES {
+            assert_eq!(from_base64_lossy(b64_data), (data.to_vec(), vec![]));
+        }
+    }
+
+    #[test]
+    fn test_decode_base64_iter() {
+        for (data, b64_data) in BASE64_VALUES {
+            let decoded: Result<Vec<u8>, _> = decode_base64_iter(b64_data.chars()).collect();
+            assert_eq!(decoded.unwrap(), data);
+        }
+
+        let wrapped = "SmU gcG\n Vuc2Ug\tZG9u  YyBqZSB\r\nzdWlzCg==";
+        let decoded: Vec<u8> = decode_base64_iter(wrapped.chars())
+            .map(|b| b.unwrap())
+            .collect();
+        assert_eq!(decoded, from_base64(wrapped).unwrap());
+
+        // Lazy: the bytes before the error are available
+        let mut decoder = decode_base64_iter("SGVs&bG8=".chars());
+        assert_eq!(decoder.next(), Some(Ok(b'H')));
+        assert_eq!(decoder.next(), Some(Ok(b'e')));
+        assert_eq!(decoder.next(), Some(Ok(b'l')));
+        assert_eq!(
+            decoder.next(),
+            Some(Err(InvalidBase64Char {
+                character: '&',
+                position: 4
+            }))
+        );
+        assert_eq!(decoder.next(), None);
+    }
+
+    #[test]
+    fn test_base64_bytes() {
+        for (data, b64_data) in BASE64_VALUES {
+            assert_eq!(to_base64_bytes(data), b64_data.as_bytes());
+            assert_eq!(to_base64_bytes(data), to_base64(data).as_bytes());
+            assert_eq!(from_base64_bytes(b64_data.as_bytes()).unwrap(), data);
+        }
+
+        assert_eq!(
+            from_base64_bytes(b"SGVsbG8s\r\nIHdvcmxkIQ==").unwrap(),
+            from_base64("SGVsbG8s\r\nIHdvcmxkIQ==").unwrap()
+        );
+        assert_eq!(
+            from_base64_bytes(b"AB\xc3\xa9CD").unwrap_err(),
+            InvalidBase64Char {
+                character: '\u{c3}',
+                position: 2
+            }
+        );
+    }
+
+    #[test]
+    fn test_frombase64_with_padding() {
+        for (data, b64_data) in BASE64_VALUES {
+            let (decoded, padding) = from_base64_with_padding(b64_data).unwrap();
+            assert_eq!(decoded, data);
+            assert_eq!(
+                padding as usize,
+                b64_data.len() - b64_data.trim_end_matches('=').len()
+            );
+        }
+
+        assert_eq!(from_base64_with_padding("Zm9vYmFy").unwrap().1, 0);
+        assert_eq!(from_base64_with_padding("AAE=").unwrap().1, 1);
+        assert_eq!(from_base64_with_padding("AA==\n").unwrap().1, 2);
+
+        // Interior `=` are not counted, and the count is clamped
+        assert_eq!(from_base64_with_padding("QQ==QQ==").unwrap().1, 2);
+        assert_eq!(from_base64_with_padding("QQ=QQ==").unwrap().1, 2);
+        assert_eq!(from_base64_with_padding("QQ==QUI=").unwrap().1, 1);
+        assert_eq!(from_base64_with_padding("QQ==QUJD").unwrap().1, 0);
+        assert_eq!(from_base64_with_padding("QQ===").unwrap().1, 2);
+    }
+
+    #[test]
+    fn test_frombase64_error_position() {
+        let error = from_base64("AB&CD").unwrap_err();
+        assert_eq!(
+            error,
+            InvalidBase64Char {
+                character: '&',
+                position: 2
+            }
+        );
+        assert_eq!(
+            error.to_string(),
+            "Invalid Base64 data, char & at position 2 is invalid"
+        );
+
+        // Whitespace is counted
+        assert_eq!(from_base64("AB\n CD\té").unwrap_err().position, 7);
+    }
+
+    #[test]
+    fn test_frombase64_crlf() {
+        let lf = "SGVsbG8sIHdv\ncmxkIQ==\n";
+        let crlf = "SGVsbG8sIHdv\r\ncmxkIQ==\r\n";
+        assert_eq!(from_base64(crlf).unwrap(), from_base64(lf).unwrap());
+        assert_eq!(from_base64(crlf).unwrap(), b"Hello, world!");
+
+        // 6_crlf.txt is 6.txt with Windows line endings
+        assert_eq!(
+            load_base64_file("6_crlf").unwrap(),
+            load_base64_file("6").unwrap()
+        );
+    }
+
     #[test]
     fn test_load_base64_file() {
         let load_result = load_base64_file("UNKNOWN");
@@ -177,4 +498,49 @@ mod tests {
         assert!(io_error.is_ok());
         assert_eq!(io_error.unwrap().kind(), ErrorKind::NotFound);
     }
+
+    #[test]
+    fn test_load_base64_file_from() {
+        let data_dir = env::temp_dir().join(format!("cryptopals_{}", std::process::id()));
+        std::fs::create_dir_all(&data_dir).unwrap();
+        std::fs::write(data_dir.join("test.txt"), "SGVsbG8sIHdvcmxkIQ==\n").unwrap();
+
+        let loaded = load_base64_file_from(&data_dir, "test");
+        std::fs::remove_dir_all(&data_dir).unwrap();
+
+        assert_eq!(loaded.unwrap(), b"Hello, world!");
+        assert!(load_base64_file_from(&data_dir, "test").is_err());
+    }
+
+    #[test]
+    fn test_data_dir_env() {
+        let data_dir = env::temp_dir().join(format!("cryptopals_env_{}", std::process::id()));
+        std::fs::create_dir_all(&data_dir).unwrap();
+        std::fs::write(data_dir.join("test.txt"), "SGVsbG8sIHdvcmxkIQ==\n").unwrap();
+
+        // The other tests read the data files concurrently, so the variable is only set in a
+        // child process running `load_base64_file_from_env`
+        let output = std::process::Command::new(env::current_exe().unwrap())
+            .args(["--exact", "base64::tests::load_base64_file_from_env"])
+            .arg("--ignored")
+            .env(DATA_DIR_ENV, &data_dir)
+            .output()
+            .unwrap();
+        std::fs::remove_dir_all(&data_dir).unwrap();
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(output.status.success(), "{stdout}");
+        assert!(stdout.contains("1 passed"), "{stdout}");
+    }
+
+    #[test]
+    #[ignore = "run by test_data_dir_env with CRYPTOPALS_DATA_DIR set"]
+    fn load_base64_file_from_env() {
+        let Some(expected_dir) = env::var_os(DATA_DIR_ENV) else {
+            return;
+        };
+        assert_eq!(data_dir(), PathBuf::from(expected_dir));
+        assert_eq!(load_base64_file("test").unwrap(), b"Hello, world!");
+        assert!(load_base64_file("7").is_err());
+    }
 }
@@ -1,19 +1,33 @@
-use std::{error::Error, fmt};
+use alloc::{vec, vec::Vec};
+use core::{
+    error::Error,
+    fmt,
+    ops::{BitXor, BitXorAssign, Deref, DerefMut, RangeInclusive},
+};
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct BlockSize {
     value: u8,
 }
 
 impl BlockSize {
     pub fn new(size: usize) -> Result<BlockSize, InvalidBlockSize> {
-        if size == 0 || size >= 256 {
-            Err(InvalidBlockSize(0))
-        } else {
+        if BlockSize::valid_range().contains(&size) {
             Ok(BlockSize { value: size as u8 })
+        } else {
+            Err(InvalidBlockSize(size))
         }
     }
 
+    pub fn value(&self) -> usize {
+        self.value as usize
+    }
+
+    /// Block sizes accepted by `BlockSize::new`
+    pub fn valid_range() -> RangeInclusive<usize> {
+        1..=255
+    }
+
     pub const AES_BLK_SZ_U8: u8 = 16;
     pub const AES_BLK_SZ_USIZE: usize = 16;
 
@@ -81,11 +95,89 @@ pub fn xor_inplace(v1: &mut [u8], v2: &[u8]) -> Result<(), IncompatibleVectorLen
     Ok(())
 }
 
+/// Byte buffer that can be XORed with `^`, to keep the bit-flipping attacks readable.
+/// XORing buffers of different lengths panics, use `xor` to handle the error instead.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Bytes(pub Vec<u8>);
+
+impl From<&[u8]> for Bytes {
+    fn from(data: &[u8]) -> Bytes {
+        Bytes(data.to_vec())
+    }
+}
+
+impl Deref for Bytes {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl DerefMut for Bytes {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        &mut self.0
+    }
+}
+
+impl BitXor for Bytes {
+    type Output = Bytes;
+
+    fn bitxor(mut self, other: Bytes) -> Bytes {
+        self ^= &other;
+        self
+    }
+}
+
+impl BitXor<&Bytes> for &Bytes {
+    type Output = Bytes;
+
+    fn bitxor(self, other: &Bytes) -> Bytes {
+        self.clone() ^ other.clone()
+    }
+}
+
+impl BitXorAssign<&Bytes> for Bytes {
+    fn bitxor_assign(&mut self, other: &Bytes) {
+        if let Err(e) = xor_inplace(self, other) {
+            panic!("Cannot XOR Bytes: {}", e);
+        }
+    }
+}
+
+/// XOR every byte of the data with the same key byte
+pub fn xor_byte(data: &[u8], key: u8) -> Vec<u8> {
+    data.iter().map(|c| c ^ key).collect()
+}
+
+/// Like `chunks_exact(16)`, but yields `&[u8; 16]` so that the blocks can be handed to the AES
+/// primitives directly. A trailing partial block is ignored.
+pub fn blocks_exact(data: &[u8]) -> impl Iterator<Item = &[u8; 16]> {
+    data.chunks_exact(BlockSize::AES_BLK_SZ_USIZE)
+        // chunks_exact guarantees the length, so the conversion can't fail
+        .map(|block| block.try_into().unwrap())
+}
+
+pub fn blocks_exact_mut(data: &mut [u8]) -> impl Iterator<Item = &mut [u8; 16]> {
+    data.chunks_exact_mut(BlockSize::AES_BLK_SZ_USIZE)
+        .map(|block| block.try_into().unwrap())
+}
+
+/// Split `data` into `block_size` slices, the last one may be shorter. Panics if `block_size` is 0.
+pub fn split_blocks(data: &[u8], block_size: usize) -> Vec<&[u8]> {
+    data.chunks(block_size).collect()
+}
+
+/// Inverse of `split_blocks`, the blocks can be reordered before joining them
+pub fn join_blocks(blocks: &[&[u8]]) -> Vec<u8> {
+    blocks.concat()
+}
+
 fn pad_block(data: &[u8], block_size: BlockSize) -> Result<Vec<u8>, DataTooLarge> {
-    if data.len() > block_size.value as usize {
+    if data.len() > block_size.value() {
         Err(DataTooLarge {
             got_size: data.len(),
-            max_size: block_size.value as usize,
+            max_size: block_size.value(),
         })
     } else {
         let padding_len = block_size.value - data.len() as u8;
@@ -98,7 +190,7 @@ fn pad_block(data: &[u8], block_size: BlockSize) -> Result<Vec<u8>, DataTooLarge
 }
 
 pub fn add_padding(data: &[u8], block_size: BlockSize) -> Result<Vec<u8>, InvalidBlockSize> {
-    let to_add = data.len() % block_size.value as usize;
+    let to_add = data.len() % block_size.value();
     let mut padded_data = Vec::from(&data[..data.len() - to_add]);
 
     // using expect because the error would not make sense to the caller,
@@ -114,6 +206,110 @@ pub fn add_padding(data: &[u8], block_size: BlockSize) -> Result<Vec<u8>, Invali
     Ok(padded_data)
 }
 
+#[derive(Debug, PartialEq)]
+pub struct UnalignedData(pub(crate) usize);
+
+impl fmt::Display for UnalignedData {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Data length {} is not a multiple of the block size",
+            self.0
+        )
+    }
+}
+
+impl Error for UnalignedData {}
+
+#[derive(Debug, PartialEq)]
+pub struct InvalidPadding();
+
+impl fmt::Display for InvalidPadding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Invalid padding")
+    }
+}
+
+impl Error for InvalidPadding {}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Padding {
+    /// n bytes of value n, always adds at least one byte
+    Pkcs7,
+    /// zeros up to the next block boundary, nothing if the data is already aligned.
+    /// It is ambiguous: trailing zeros of the data are removed along with the padding.
+    ZeroPad,
+    /// n-1 zeros followed by the value n, always adds at least one byte
+    AnsiX923,
+    /// the data must already be aligned on the block size
+    NoPadding,
+}
+
+impl Padding {
+    pub fn pad(self, data: &[u8], block_size: BlockSize) -> Result<Vec<u8>, UnalignedData> {
+        let to_add = block_size.value() - data.len() % block_size.value();
+        match self {
+            // add_padding can't fail with a valid BlockSize
+            Padding::Pkcs7 => Ok(add_padding(data, block_size).unwrap()),
+            Padding::ZeroPad => {
+                let to_add = to_add % block_size.value();
+                Ok([data, &vec![0; to_add]].concat())
+            }
+            Padding::AnsiX923 => Ok([data, &vec![0; to_add - 1], &[to_add as u8]].concat()),
+            Padding::NoPadding => {
+                if !data.len().is_multiple_of(block_size.value()) {
+                    Err(UnalignedData(data.len()))
+                } else {
+                    Ok(data.to_vec())
+                }
+            }
+        }
+    }
+
+    /// Returns the data without its padding
+    pub fn unpad(self, data: &[u8], block_size: BlockSize) -> Result<&[u8], InvalidPadding> {
+        match self {
+            Padding::Pkcs7 | Padding::AnsiX923 => {
+                let padding_len = *data.last().ok_or(InvalidPadding())? as usize;
+                if padding_len == 0 || padding_len > block_size.value() || padding_len > data.len()
+                {
+                    return Err(InvalidPadding());
+                }
+                let (unpadded, padding) = data.split_at(data.len() - padding_len);
+                let filler = if self == Padding::Pkcs7 {
+                    padding_len as u8
+                } else {
+                    0
+                };
+                if padding[..padding_len - 1].iter().all(|&b| b == filler) {
+                    Ok(unpadded)
+                } else {
+                    Err(InvalidPadding())
+                }
+            }
+            Padding::ZeroPad => {
+                let data_len = data.iter().rposition(|&b| b != 0).map_or(0, |p| p + 1);
+                Ok(&data[..data_len])
+            }
+            Padding::NoPadding => Ok(data),
+        }
+    }
+}
+
+/// A cipher that XORs the data with a keystream which doesn't depend on the data, so that the
+/// attacks on keystream reuse work the same on all of them. CFB doesn't fit: its keystream
+/// depends on the ciphertext.
+pub trait StreamCipher {
+    fn keystream(&self, len: usize) -> Vec<u8>;
+
+    /// Encrypts or decrypts the data, it is the same operation
+    fn apply(&self, data: &[u8]) -> Vec<u8> {
+        let mut output = self.keystream(data.len());
+        output.iter_mut().zip(data).for_each(|(k, d)| *k ^= d);
+        output
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::block::*;
@@ -155,12 +351,125 @@ mod tests {
             Err(IncompatibleVectorLength(4, 1))
         );
     }
+
+    #[test]
+    fn test_xor_byte() {
+        assert_eq!(xor_byte(b"ABC", 0x20), b"abc");
+        assert_eq!(xor_byte(b"ABC", 0), b"ABC");
+        assert!(xor_byte(&[], 42).is_empty());
+    }
+    #[test]
+    fn test_blocks_exact() {
+        assert_eq!(blocks_exact(&[]).count(), 0);
+        assert_eq!(blocks_exact(&[1; 15]).count(), 0);
+        assert_eq!(blocks_exact(&[1; 16]).collect::<Vec<_>>(), vec![&[1; 16]]);
+
+        let data: Vec<u8> = (0..40).collect();
+        let blocks: Vec<&[u8; 16]> = blocks_exact(&data).collect();
+        assert_eq!(blocks.len(), 2);
+        assert_eq!(blocks[0][..], data[..16]);
+        assert_eq!(blocks[1][..], data[16..32]);
+
+        let mut data = [0u8; 40];
+        for (index, block) in blocks_exact_mut(&mut data).enumerate() {
+            block.fill(index as u8 + 1);
+        }
+        assert_eq!(data[..16], [1; 16]);
+        assert_eq!(data[16..32], [2; 16]);
+        assert_eq!(data[32..], [0; 8]);
+    }
+
+    #[test]
+    fn test_split_blocks() {
+        let data: Vec<u8> = (0..48).collect();
+        let blocks = split_blocks(&data, 16);
+        assert_eq!(blocks.len(), 3);
+        assert_eq!(blocks[1], &data[16..32]);
+        assert_eq!(join_blocks(&blocks), data);
+
+        let reordered = join_blocks(&[blocks[2], blocks[0]]);
+        assert_eq!(reordered[..16], data[32..]);
+        assert_eq!(reordered[16..], data[..16]);
+
+        let blocks = split_blocks(&data[..20], 16);
+        assert_eq!(blocks[1], &data[16..20]);
+        assert!(split_blocks(&[], 16).is_empty());
+    }
+
     #[test]
     fn test_block_size() {
         assert!(BlockSize::new(0).is_err());
         assert!(BlockSize::new(300).is_err());
         assert!(BlockSize::new(257).is_err());
-        assert!(BlockSize::new(10).is_ok())
+        assert!(BlockSize::new(10).is_ok());
+        assert!(BlockSize::new(1).is_ok());
+        assert!(BlockSize::new(255).is_ok());
+        assert!(BlockSize::new(256).is_err());
+
+        assert_eq!(BlockSize::new(300).unwrap_err(), InvalidBlockSize(300));
+        assert!(BlockSize::new(300).unwrap_err().to_string().contains("300"));
+        assert_eq!(BlockSize::new(0).unwrap_err(), InvalidBlockSize(0));
+    }
+
+    #[test]
+    fn test_block_size_value() {
+        assert_eq!(BlockSize::AES_BLK_SZ.value(), 16);
+        assert_eq!(BlockSize::new(255).unwrap().value(), 255);
+        assert_eq!(BlockSize::new(1).unwrap().value(), 1);
+    }
+
+    #[test]
+    fn test_block_size_hash() {
+        use std::collections::HashSet;
+
+        let sizes: HashSet<BlockSize> = [BlockSize::AES_BLK_SZ, BlockSize::new(8).unwrap()].into();
+        assert!(sizes.contains(&BlockSize::new(16).unwrap()));
+        assert!(sizes.contains(&BlockSize::new(8).unwrap()));
+        assert!(!sizes.contains(&BlockSize::new(32).unwrap()));
+        assert_eq!(BlockSize::new(16).unwrap(), BlockSize::AES_BLK_SZ);
+    }
+
+    #[test]
+    fn test_padding_schemes() {
+        let blk_sz_4 = BlockSize::new(4).unwrap();
+
+        for (padding, data, padded) in [
+            (Padding::Pkcs7, vec![1, 2, 3], vec![1, 2, 3, 1]),
+            (
+                Padding::Pkcs7,
+                vec![1, 2, 3, 4],
+                vec![1, 2, 3, 4, 4, 4, 4, 4],
+            ),
+            (Padding::ZeroPad, vec![1, 2], vec![1, 2, 0, 0]),
+            (Padding::ZeroPad, vec![1, 2, 3, 4], vec![1, 2, 3, 4]),
+            (Padding::AnsiX923, vec![1], vec![1, 0, 0, 3]),
+            (Padding::AnsiX923, vec![], vec![0, 0, 0, 4]),
+            (Padding::NoPadding, vec![1, 2, 3, 4], vec![1, 2, 3, 4]),
+        ] {
+            assert_eq!(padding.pad(&data, blk_sz_4).unwrap(), padded);
+            assert_eq!(padding.unpad(&padded, blk_sz_4).unwrap(), data);
+        }
+
+        assert_eq!(
+            Padding::NoPadding.pad(&[1, 2, 3], blk_sz_4),
+            Err(UnalignedData(3))
+        );
+
+        // Zero padding can't tell the data zeros from the padding
+        let padded = Padding::ZeroPad.pad(&[1, 0], blk_sz_4).unwrap();
+        assert_eq!(Padding::ZeroPad.unpad(&padded, blk_sz_4).unwrap(), [1]);
+
+        for (padding, invalid) in [
+            (Padding::Pkcs7, vec![]),
+            (Padding::Pkcs7, vec![1, 2, 3, 0]),
+            (Padding::Pkcs7, vec![1, 2, 3, 5]),
+            (Padding::Pkcs7, vec![1, 2, 3, 2]),
+            (Padding::Pkcs7, vec![3, 3]),
+            (Padding::AnsiX923, vec![1, 0, 1, 3]),
+            (Padding::AnsiX923, vec![1, 2, 3, 0]),
+        ] {
+            assert_eq!(padding.unpad(&invalid, blk_sz_4), Err(InvalidPadding()));
+        }
     }
 
     #[test]
@@ -184,4 +493,24 @@ mod tests {
             vec![1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 2, 2]
         );
     }
+
+    #[test]
+    fn test_bytes_xor() {
+        assert_eq!(Bytes(vec![1, 2]) ^ Bytes(vec![3, 1]), Bytes(vec![2, 3]));
+        assert_eq!(&Bytes(vec![1, 2]) ^ &Bytes(vec![1, 2]), Bytes(vec![0, 0]));
+        assert_eq!(Bytes::default() ^ Bytes(vec![]), Bytes(vec![]));
+
+        let mut bytes = Bytes::from(&b"YELLOW"[..]);
+        bytes ^= &Bytes(vec![0x20; 6]);
+        assert_eq!(&*bytes, b"yellow");
+        bytes[0] = b'm';
+        assert_eq!(bytes.len(), 6);
+        assert_eq!(xor(&bytes, b"mellow").unwrap(), [0; 6]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Incompatible vector lengths: 2 and 1")]
+    fn test_bytes_xor_length_mismatch() {
+        let _ = Bytes(vec![1, 2]) ^ Bytes(vec![3]);
+    }
 }
@@ -1,5 +1,11 @@
 use std::{collections::HashMap, error::Error, fmt::Display};
 
+use crate::aes::decrypt_ecb;
+use crate::block::{blocks_exact, xor, BlockSize, Padding};
+use crate::decrypt::{find_key_block_xor_with, EnglishCharFreq};
+use crate::profile::Challenge13Oracle;
+use crate::BoxResult;
+
 #[derive(Debug)]
 pub struct InternalError(String);
 
@@ -11,20 +17,97 @@ impl Display for InternalError {
 
 impl Error for InternalError {}
 
-pub fn crack_ecb(
-    generator: impl Fn(&[u8]) -> Result<Vec<u8>, Box<dyn Error>>,
-) -> Result<Vec<u8>, Box<dyn Error>> {
-    let block_size = 16;
-    let number_of_blocks = generator(&[])?.len() / block_size;
-
-    let mut plaintexts: Vec<Vec<u8>> = vec![];
-    // We are basically going to loop over each block, solving them one after the other
-    for block_idx in 0..number_of_blocks {
-        let mut bait_block: Vec<u8> = if !plaintexts.is_empty() {
-            plaintexts.last().cloned().unwrap()
-        } else {
-            vec![0; block_size]
+pub fn crack_ecb<E: Into<Box<dyn Error>>>(
+    generator: impl Fn(&[u8]) -> Result<Vec<u8>, E>,
+) -> BoxResult<Vec<u8>> {
+    let mut cracker = EcbCracker::new(generator);
+    Ok(cracker.step(usize::MAX)?.to_vec())
+}
+
+/// Same as `crack_ecb`, but stops as soon as `sentinel` is recovered, for secrets with a known
+/// terminator. The sentinel is not part of the result.
+pub fn crack_ecb_until<E: Into<Box<dyn Error>>>(
+    generator: impl Fn(&[u8]) -> Result<Vec<u8>, E>,
+    sentinel: u8,
+) -> BoxResult<Vec<u8>> {
+    let mut cracker = EcbCracker::new(generator);
+    while !cracker.done() {
+        let plaintext = cracker.step(1)?;
+        if let Some(position) = plaintext.iter().position(|&b| b == sentinel) {
+            return Ok(plaintext[..position].to_vec());
+        }
+    }
+    Ok(cracker.step(0)?.to_vec())
+}
+
+/// Resumable version of `crack_ecb`, recovering the secret a few bytes at a time
+pub struct EcbCracker<G> {
+    generator: G,
+    // Computed on the first step
+    number_of_blocks: Option<usize>,
+    plaintext: Vec<u8>,
+    bait_block: Vec<u8>,
+    done: bool,
+}
+
+impl<G, E> EcbCracker<G>
+where
+    G: Fn(&[u8]) -> Result<Vec<u8>, E>,
+    E: Into<Box<dyn Error>>,
+{
+    pub fn new(generator: G) -> EcbCracker<G> {
+        EcbCracker {
+            generator,
+            number_of_blocks: None,
+            plaintext: vec![],
+            bait_block: vec![],
+            done: false,
+        }
+    }
+
+    /// Recovers up to `max_bytes` more bytes, returns everything recovered so far
+    pub fn step(&mut self, max_bytes: usize) -> BoxResult<&[u8]> {
+        for _ in 0..max_bytes {
+            if self.done {
+                break;
+            }
+            self.next_byte()?;
+        }
+        Ok(&self.plaintext)
+    }
+
+    pub fn done(&self) -> bool {
+        self.done
+    }
+
+    fn generate(&self, data: &[u8]) -> BoxResult<Vec<u8>> {
+        (self.generator)(data).map_err(Into::into)
+    }
+
+    fn next_byte(&mut self) -> BoxResult<()> {
+        let block_size = 16;
+        let number_of_blocks = match self.number_of_blocks {
+            Some(number_of_blocks) => number_of_blocks,
+            None => {
+                let number_of_blocks = self.generate(&[])?.len() / block_size;
+                self.number_of_blocks = Some(number_of_blocks);
+                number_of_blocks
+            }
         };
+        // We are basically going to loop over each block, solving them one after the other
+        let block_idx = self.plaintext.len() / block_size;
+        if block_idx >= number_of_blocks {
+            self.done = true;
+            return Ok(());
+        }
+        if self.plaintext.len().is_multiple_of(block_size) {
+            self.bait_block = if block_idx > 0 {
+                self.plaintext[(block_idx - 1) * block_size..].to_vec()
+            } else {
+                vec![0; block_size]
+            };
+        }
+
         // The trick is that if you know the left part of a message, and can prefix an arbitrary
         // block of data, then you can deduce the next byte in the message. To do that you will
         // need to know the content of a plain text block, and have the ability to shift the entire
@@ -32,36 +115,468 @@ pub fn crack_ecb(
         // add a new byte, there are only 256 possible values for that byte, and you know the rest
         // of the block, so you can compute the cyphertext for each of them and compare them with
         // the one from the message itself.
-        let mut plain_block = vec![];
-        for _ in 0..bait_block.len() {
-            bait_block.remove(0);
-            let mut candidates = HashMap::<Vec<u8>, u8>::new();
-            for c in 0..=255u8 {
-                let mut candidate = [bait_block.clone(), plain_block.clone()].concat();
-                candidate.push(c);
-                let encrypted = generator(&candidate)?.get(0..16).unwrap().to_owned();
-                candidates.insert(encrypted, c);
+        self.bait_block.remove(0);
+        let plain_block = &self.plaintext[block_idx * block_size..];
+        let mut candidates = HashMap::<Vec<u8>, u8>::new();
+        for c in 0..=255u8 {
+            let candidate = [self.bait_block.as_slice(), plain_block, &[c]].concat();
+            let encrypted = self.generate(&candidate)?.get(0..16).unwrap().to_owned();
+            candidates.insert(encrypted, c);
+        }
+        let actual = self
+            .generate(&self.bait_block)?
+            .get(block_idx * 16..block_idx * 16 + 16)
+            .unwrap()
+            .to_owned();
+        match candidates.get(&actual) {
+            Some(&c) => {
+                self.plaintext.push(c);
+                if self.plaintext.len() == number_of_blocks * block_size {
+                    self.done = true;
+                }
+                Ok(())
+            }
+            // If we are on the last block, we are hitting the dynamic part of the message
+            // (padding)
+            None if block_idx == number_of_blocks - 1 => {
+                self.plaintext.pop();
+                self.done = true;
+                Ok(())
             }
-            let actual = generator(&bait_block)?
-                .get(plaintexts.len() * 16..plaintexts.len() * 16 + 16)
-                .unwrap()
-                .to_owned();
-            if !candidates.contains_key(&actual) {
-                // If we are on the last block, we are hitting the dynamic part of the message
-                // (padding)
-                if block_idx == number_of_blocks - 1 {
-                    plain_block.pop();
-                    plaintexts.push(plain_block);
-                    return Ok(plaintexts.concat());
+            None => {
+                Err(InternalError("Could not find a matching encrypted message".to_string()).into())
+            }
+        }
+    }
+}
+
+/// Length of the fixed prefix an ECB oracle adds before our data (challenge 14). We send filler
+/// bytes followed by two identical blocks: once the filler completes the last block of the
+/// prefix, the two blocks are encrypted identically. This is done with two different baits, the
+/// bait blocks are the identical pair that changes with the bait, so that repeated blocks of the
+/// prefix or of the secret are not mistaken for them.
+///
+/// Panics if the oracle doesn't look like ECB.
+pub fn ecb_prefix_len(oracle: impl Fn(&[u8]) -> Vec<u8>, block_size: usize) -> usize {
+    for filler_len in 0..block_size {
+        let [output_a, output_b] = [b'A', b'B']
+            .map(|bait| oracle(&[vec![!bait; filler_len], vec![bait; 2 * block_size]].concat()));
+        let blocks_a: Vec<&[u8]> = output_a.chunks_exact(block_size).collect();
+        let blocks_b: Vec<&[u8]> = output_b.chunks_exact(block_size).collect();
+        let bait_index = blocks_a
+            .windows(2)
+            .zip(blocks_b.windows(2))
+            .position(|(a, b)| a[0] == a[1] && b[0] == b[1] && a[0] != b[0]);
+        if let Some(index) = bait_index {
+            // The bait blocks come after the prefix and the filler
+            return index * block_size - filler_len;
+        }
+    }
+    panic!("The oracle doesn't look like ECB");
+}
+
+/// Exact number of bytes an ECB oracle with PKCS#7 padding appends to our data (challenge 12),
+/// the prefix included if there is one. We add filler bytes until the output grows by a block:
+/// at that point the filler and the secret fill the previous output exactly.
+pub fn ecb_secret_len(generator: impl Fn(&[u8]) -> Vec<u8>, block_size: usize) -> usize {
+    let base_len = generator(&[]).len();
+    for filler_len in 1..=block_size {
+        if generator(&vec![0; filler_len]).len() > base_len {
+            return base_len - filler_len;
+        }
+    }
+    // Cannot happen with PKCS#7, which always adds between 1 and block_size bytes
+    base_len
+}
+
+/// Keystream of a CTR ciphertext over the part of the plaintext we know, from its beginning.
+/// Any other message encrypted with the same key and nonce can be decrypted with it, without
+/// knowing the key or the nonce.
+pub fn ctr_keystream_from_known_plaintext(ciphertext: &[u8], known_plaintext: &[u8]) -> Vec<u8> {
+    ciphertext
+        .iter()
+        .zip(known_plaintext)
+        .map(|(c, p)| c ^ p)
+        .collect()
+}
+
+/// Statistical attack on CTR ciphertexts encrypted with the same key and nonce (challenge 20):
+/// they all share the same keystream, as with any `StreamCipher` reused without a nonce. Once
+/// truncated to the shortest one, this is a repeating-key XOR with a key as long as the
+/// ciphertexts. Returns the recovered keystream, as long as the shortest ciphertext, and the
+/// truncated plaintexts.
+/// The case of a column without any space can't be known for sure, the first character of the
+/// plaintexts is usually lowercase when it should be uppercase.
+pub fn crack_fixed_nonce_ctr(ciphertexts: &[Vec<u8>]) -> Option<(Vec<u8>, Vec<Vec<u8>>)> {
+    let len = ciphertexts.iter().map(|c| c.len()).min()?;
+    let truncated: Vec<u8> = ciphertexts
+        .iter()
+        .flat_map(|c| &c[..len])
+        .copied()
+        .collect();
+    let keystream = find_key_block_xor_with::<EnglishCharFreq>(&truncated, len)?;
+    let plaintexts = ciphertexts
+        .iter()
+        // Same length by construction
+        .map(|c| xor(&c[..len], &keystream).unwrap())
+        .collect();
+    Some((keystream, plaintexts))
+}
+
+/// CBC padding oracle attack. `oracle(ciphertext, iv)` tells whether the ciphertext decrypted
+/// with that IV is accepted. Any oracle works as long as accepting the message implies that its
+/// PKCS#7 padding is valid, e.g. "valid padding" or "valid padding and ASCII plaintext".
+/// The oracle is only ever called with single-block ciphertexts.
+pub fn crack_cbc_padding_oracle(
+    ciphertext: &[u8],
+    iv: &[u8; 16],
+    oracle: impl Fn(&[u8], &[u8; 16]) -> bool,
+) -> BoxResult<Vec<u8>> {
+    if ciphertext.is_empty() || !ciphertext.len().is_multiple_of(16) {
+        return Err(
+            InternalError(format!("Invalid ciphertext length {}", ciphertext.len())).into(),
+        );
+    }
+
+    let mut plaintext = Vec::with_capacity(ciphertext.len());
+    let mut previous_block = *iv;
+    for block in blocks_exact(ciphertext) {
+        // The block decrypted by AES, before the xor with the previous block
+        let mut intermediate = [0u8; 16];
+        for position in (0..16).rev() {
+            let padding = (16 - position) as u8;
+            // Start from the real previous block so that the bytes we don't touch decrypt to the
+            // original plaintext, which keeps oracles checking the content happy.
+            let mut forged = previous_block;
+            for i in position + 1..16 {
+                forged[i] = intermediate[i] ^ padding;
+            }
+
+            let mut found = None;
+            for candidate in 0..=255u8 {
+                forged[position] = candidate;
+                if !oracle(block, &forged) {
+                    continue;
+                }
+                if position == 15 {
+                    // The padding may be \x02\x02 (or longer) instead of \x01: changing the
+                    // second to last byte makes sure it isn't.
+                    let mut check = forged;
+                    check[14] ^= 1;
+                    if !oracle(block, &check) {
+                        continue;
+                    }
                 }
-                return Err(InternalError(
-                    "Could not find a matching encrypted message".to_string(),
-                )
-                .into());
+                found = Some(candidate);
+                break;
             }
-            plain_block.push(candidates[&actual]);
+            let candidate = found.ok_or_else(|| {
+                InternalError(format!(
+                    "The oracle rejected every value for byte {}",
+                    position
+                ))
+            })?;
+            intermediate[position] = candidate ^ padding;
         }
-        plaintexts.push(plain_block.clone());
+        plaintext.extend(intermediate.iter().zip(previous_block).map(|(i, p)| i ^ p));
+        previous_block = *block;
+    }
+
+    let unpadded_len = Padding::Pkcs7
+        .unpad(&plaintext, BlockSize::AES_BLK_SZ)?
+        .len();
+    plaintext.truncate(unpadded_len);
+    Ok(plaintext)
+}
+
+/// Tries to decrypt the ECB ciphertext with each key, returns the first key (and the plaintext)
+/// for which the decryption succeeds and `validate` accepts the plaintext.
+pub fn brute_force_key(
+    ciphertext: &[u8],
+    candidate_keys: impl Iterator<Item = [u8; 16]>,
+    validate: impl Fn(&[u8]) -> bool,
+) -> Option<([u8; 16], Vec<u8>)> {
+    candidate_keys
+        .filter_map(|key| Some((key, decrypt_ecb(ciphertext, &key).ok()?)))
+        .find(|(_, plaintext)| validate(plaintext))
+}
+
+/// Cut-and-paste attack: builds a ciphertext for an admin profile using only the oracle
+pub fn forge_admin_profile(oracle: &Challenge13Oracle) -> BoxResult<Vec<u8>> {
+    let block_size = BlockSize::AES_BLK_SZ_USIZE;
+    // "email=" + 10 bytes completes the first block, the second one is a padded "admin"
+    let admin_block = Padding::Pkcs7.pad(b"admin", BlockSize::AES_BLK_SZ)?;
+    let bait = [b"AAAAAAAAAA".as_slice(), &admin_block].concat();
+    let encrypted_bait = oracle.encrypt_profile(std::str::from_utf8(&bait)?)?;
+
+    // "email=" + 13 bytes + "&uid=10&role=" is exactly 2 blocks, "user" is in the third one
+    let encrypted_profile = oracle.encrypt_profile("foo@baar.coom")?;
+    Ok([
+        &encrypted_profile[..2 * block_size],
+        &encrypted_bait[block_size..2 * block_size],
+    ]
+    .concat())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::aes::{
+        ctr_keystream, decrypt_cbc, ecb_append_oracle, encrypt_cbc, encrypt_ecb,
+        encrypt_many_fixed_nonce, AesOfb, CounterEndian,
+    };
+    use crate::block::{BlockSize, Padding, StreamCipher, UnalignedData};
+    use crate::crack::*;
+    use crate::mt19937::Mt19937Cipher;
+
+    // Not AES, but encrypts each block independently which is all the attack needs
+    fn toy_ecb(secret: &[u8]) -> impl Fn(&[u8]) -> Result<Vec<u8>, UnalignedData> + '_ {
+        let key = b"YELLOW SUBMARINE";
+        move |data: &[u8]| {
+            let padded = Padding::Pkcs7.pad(&[data, secret].concat(), BlockSize::AES_BLK_SZ)?;
+            Ok(padded
+                .iter()
+                .enumerate()
+                .map(|(i, b)| b.rotate_left(3) ^ key[i % 16])
+                .collect())
+        }
+    }
+
+    #[test]
+    fn test_crack_ecb_concrete_error() {
+        let secret = b"Attack at dawn, bring snacks";
+        assert_eq!(crack_ecb(toy_ecb(secret)).unwrap(), secret);
+    }
+
+    #[test]
+    fn test_crack_ecb_append_oracle() {
+        let secret = b"A custom secret, a bit longer than a block".to_vec();
+        let oracle = ecb_append_oracle(secret.clone());
+        assert_eq!(oracle(b"").unwrap().len(), 48);
+        assert_eq!(crack_ecb(oracle).unwrap(), secret);
+        assert_eq!(crack_ecb(ecb_append_oracle(vec![])).unwrap(), b"");
+    }
+
+    #[test]
+    fn test_crack_ecb_until() {
+        let secret = b"null terminated\0garbage after the terminator";
+        assert_eq!(
+            crack_ecb_until(toy_ecb(secret), 0).unwrap(),
+            b"null terminated"
+        );
+        // No sentinel: same as crack_ecb
+        assert_eq!(
+            crack_ecb_until(toy_ecb(b"no terminator"), 0).unwrap(),
+            b"no terminator"
+        );
+    }
+
+    #[test]
+    fn test_ecb_cracker() {
+        let secret = b"Attack at dawn, bring snacks and a thermos of coffee";
+        let mut cracker = EcbCracker::new(toy_ecb(secret));
+
+        let mut steps = 0;
+        while !cracker.done() {
+            let recovered = cracker.step(5).unwrap();
+            steps += 1;
+            assert!(recovered.len() <= steps * 5);
+            assert!(secret.starts_with(recovered));
+        }
+        assert_eq!(cracker.step(5).unwrap(), secret);
+        assert_eq!(
+            cracker.step(5).unwrap(),
+            crack_ecb(toy_ecb(secret)).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_ecb_prefix_len() {
+        let key = b"YELLOW SUBMARINE";
+        for prefix in [
+            vec![],
+            vec![42],
+            (0..16).collect(),
+            (0..17).collect(),
+            b"ends with the bait AAA".to_vec(),
+            // Repeated blocks in the prefix, equal to the bait or not
+            vec![b'A'; 32],
+            [vec![b'X'; 32], b"tail".to_vec()].concat(),
+        ] {
+            let oracle = |data: &[u8]| {
+                encrypt_ecb([prefix.as_slice(), data, b"secret"].concat(), key).unwrap()
+            };
+            assert_eq!(ecb_prefix_len(oracle, 16), prefix.len());
+        }
+
+        // Repeated blocks in the secret
+        let oracle = |data: &[u8]| encrypt_ecb([b"prefix", data, &[0; 48]].concat(), key).unwrap();
+        assert_eq!(ecb_prefix_len(oracle, 16), 6);
+    }
+
+    #[test]
+    #[should_panic(expected = "doesn't look like ECB")]
+    fn test_ecb_prefix_len_cbc() {
+        let key = b"YELLOW SUBMARINE";
+        ecb_prefix_len(|data| encrypt_cbc(data, &[0; 16], key).unwrap(), 16);
+    }
+
+    #[test]
+    fn test_ecb_secret_len() {
+        let key = b"YELLOW SUBMARINE";
+        for secret_len in [0, 1, 15, 16, 17, 138] {
+            let secret = vec![b'S'; secret_len];
+            let oracle = |data: &[u8]| encrypt_ecb([data, &secret].concat(), key).unwrap();
+            assert_eq!(ecb_secret_len(oracle, 16), secret_len);
+
+            let toy = toy_ecb(&secret);
+            assert_eq!(ecb_secret_len(|data| toy(data).unwrap(), 16), secret_len);
+        }
+    }
+
+    #[test]
+    fn test_ctr_keystream_from_known_plaintext() {
+        let key = b"YELLOW SUBMARINE";
+        let known: &[u8] = b"Now that the party is jumping, with the bass kicked in";
+        let secret: &[u8] = b"Cooking MC's like a pound of bacon";
+        let ciphertexts = encrypt_many_fixed_nonce(&[known, secret], key, 7).unwrap();
+
+        let keystream = ctr_keystream_from_known_plaintext(&ciphertexts[0], known);
+        assert_eq!(
+            keystream,
+            ctr_keystream(key, 7, CounterEndian::Little, 0, known.len()).unwrap()
+        );
+        let decrypted = xor(&ciphertexts[1], &keystream[..secret.len()]).unwrap();
+        assert_eq!(decrypted, secret);
+
+        // Only a prefix is known
+        let keystream = ctr_keystream_from_known_plaintext(&ciphertexts[0], &known[..8]);
+        assert_eq!(keystream.len(), 8);
+        assert_eq!(xor(&ciphertexts[1][..8], &keystream).unwrap(), b"Cooking ");
+        assert!(ctr_keystream_from_known_plaintext(&[], known).is_empty());
+    }
+
+    #[test]
+    fn test_crack_fixed_nonce_ctr() {
+        // Challenge 19
+        let plaintexts: Vec<&[u8]> = vec![
+            b"I have met them at close of day",
+            b"Coming with vivid faces",
+            b"From counter or desk among grey",
+            b"Eighteenth-century houses.",
+            b"I have passed with a nod of the head",
+            b"Or polite meaningless words,",
+            b"Or have lingered awhile and said",
+            b"Polite meaningless words,",
+            b"And thought before I had done",
+            b"Of a mocking tale or a gibe",
+            b"To please a companion",
+            b"Around the fire at the club,",
+            b"Being certain that they and I",
+            b"But lived where motley is worn:",
+            b"All changed, changed utterly:",
+            b"A terrible beauty is born.",
+            b"That woman's days were spent",
+            b"In ignorant good will,",
+            b"Her nights in argument",
+            b"Until her voice grew shrill.",
+            b"What voice more sweet than hers",
+            b"When young and beautiful,",
+            b"She rode to harriers?",
+            b"This man had kept a school",
+            b"And rode our winged horse.",
+        ];
+        let key = b"YELLOW SUBMARINE";
+        let ciphertexts = encrypt_many_fixed_nonce(&plaintexts, key, 0).unwrap();
+        // "She rode to harriers?"
+        let shortest = 21;
+
+        let (keystream, decrypted) = crack_fixed_nonce_ctr(&ciphertexts).unwrap();
+        let expected_keystream = ctr_keystream(key, 0, CounterEndian::Little, 0, shortest).unwrap();
+        assert_eq!(keystream.len(), shortest);
+        // The first column has no space, only its case is wrong
+        assert_eq!(keystream[0] ^ expected_keystream[0], 0x20);
+        assert_eq!(keystream[1..], expected_keystream[1..]);
+        for (decrypted, plaintext) in decrypted.iter().zip(&plaintexts) {
+            assert!(decrypted.eq_ignore_ascii_case(&plaintext[..shortest]));
+            assert_eq!(decrypted[1..], plaintext[1..shortest]);
+        }
+
+        // Another message encrypted with the same nonce
+        let held_out = encrypt_many_fixed_nonce(&[b"He might have won fame"], key, 0).unwrap();
+        assert_eq!(
+            xor(&held_out[0][..shortest], &keystream).unwrap(),
+            b"he might have won fam"
+        );
+
+        assert!(crack_fixed_nonce_ctr(&[]).is_none());
+
+        // The attack only relies on the keystream reuse, not on CTR
+        let ciphers: Vec<Box<dyn StreamCipher>> = vec![
+            Box::new(AesOfb::new(key, b"ivIVivIVivIVivIV").unwrap()),
+            Box::new(Mt19937Cipher::new(1234)),
+        ];
+        for cipher in ciphers {
+            let ciphertexts: Vec<Vec<u8>> = plaintexts.iter().map(|p| cipher.apply(p)).collect();
+            let (keystream, decrypted) = crack_fixed_nonce_ctr(&ciphertexts).unwrap();
+            assert_eq!(keystream[1..], cipher.keystream(shortest)[1..]);
+            for (decrypted, plaintext) in decrypted.iter().zip(&plaintexts) {
+                assert!(decrypted.eq_ignore_ascii_case(&plaintext[..shortest]));
+            }
+        }
+    }
+
+    #[test]
+    fn test_crack_cbc_padding_oracle() {
+        let key = b"YELLOW SUBMARINE";
+        let iv = b"0123456789ABCDEF";
+        for message in [
+            b"Hello, world".as_slice(),
+            b"YELLOW SUBMARINE",
+            b"This message spans several blocks\x02\x02",
+        ] {
+            let ciphertext = encrypt_cbc(message, iv, key).unwrap();
+
+            let padding_oracle = |data: &[u8], iv: &[u8; 16]| decrypt_cbc(data, iv, key).is_ok();
+            assert_eq!(
+                crack_cbc_padding_oracle(&ciphertext, iv, padding_oracle).unwrap(),
+                message
+            );
+
+            let ascii_oracle = |data: &[u8], iv: &[u8; 16]| {
+                decrypt_cbc(data, iv, key).is_ok_and(|plaintext| plaintext.is_ascii())
+            };
+            assert_eq!(
+                crack_cbc_padding_oracle(&ciphertext, iv, ascii_oracle).unwrap(),
+                message
+            );
+        }
+
+        assert!(crack_cbc_padding_oracle(&[0; 15], iv, |_, _| true).is_err());
+        assert!(crack_cbc_padding_oracle(&[0; 16], iv, |_, _| false).is_err());
+    }
+
+    #[test]
+    fn test_brute_force_key() {
+        // Weak keys: a single byte repeated
+        let key = [42u8; 16];
+        let ciphertext = encrypt_ecb(b"The secret password is hunter2", &key).unwrap();
+        let crib = |plaintext: &[u8]| plaintext.windows(8).any(|w| w == b"password");
+
+        let (found_key, plaintext) =
+            brute_force_key(&ciphertext, (0..=255u8).map(|b| [b; 16]), crib).unwrap();
+        assert_eq!(found_key, key);
+        assert_eq!(plaintext, b"The secret password is hunter2");
+
+        assert!(brute_force_key(&ciphertext, (0..42u8).map(|b| [b; 16]), crib).is_none());
+    }
+
+    #[test]
+    fn test_forge_admin_profile() {
+        let oracle = Challenge13Oracle::new().unwrap();
+        let profile = oracle
+            .decrypt_and_parse(&forge_admin_profile(&oracle).unwrap())
+            .unwrap();
+        assert_eq!(profile["role"], "admin");
+        assert_eq!(profile["email"], "foo@baar.coom");
     }
-    Ok(plaintexts.concat())
 }
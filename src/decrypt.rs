@@ -1,60 +1,228 @@
 extern crate log;
 
-use log::debug;
-use std::{
-    cmp::{min, Ordering},
-    collections::HashSet,
+use alloc::{
+    collections::BTreeSet,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+use core::{
+    cmp::{max, min, Ordering},
+    ops::RangeInclusive,
 };
+use log::debug;
+
+use crate::block::xor_byte;
+
+/// Renders bytes for logging, replacing anything outside of the printable ASCII range
+/// by `.` like the gutter of a hexdump. Never fails, unlike `String::from_utf8`.
+pub fn to_printable(data: &[u8]) -> String {
+    data.iter()
+        .map(|&b| {
+            if b == b' ' || b.is_ascii_graphic() {
+                b as char
+            } else {
+                '.'
+            }
+        })
+        .collect()
+}
 
 pub trait ScoringFunction {
     fn score(data: &[u8]) -> Option<f32>;
 }
 
-pub struct EnglishLetterFreq();
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Language {
+    English,
+    French,
+    German,
+}
 
-impl ScoringFunction for EnglishLetterFreq {
-    fn score(data: &[u8]) -> Option<f32> {
-        let mut letter_stats = [0; 26];
-        for c in data {
-            if c.is_ascii_alphabetic() {
-                letter_stats[c.to_ascii_lowercase() as usize - 'a' as usize] += 1;
-            } else if !(c.is_ascii_alphanumeric()
-                || c.is_ascii_whitespace()
-                || c.is_ascii_punctuation())
-            {
-                return None;
-            }
+// Letter frequencies, from a to z
+// https://en.wikipedia.org/wiki/Letter_frequency
+const ENGLISH_LETTER_FREQ: [f32; 26] = [
+    0.082, 0.015, 0.028, 0.043, 0.127, 0.022, // F
+    0.02, 0.061, 0.07, 0.0015, 0.0077, 0.04, // L
+    0.024, 0.067, 0.075, 0.019, 0.00095, 0.06, // R
+    0.063, 0.091, 0.028, 0.0098, 0.024, 0.0015, // X
+    0.02, 0.00074, // Z
+];
+
+const FRENCH_LETTER_FREQ: [f32; 26] = [
+    0.07636, 0.00901, 0.0326, 0.03669, 0.14715, 0.01066, // F
+    0.00866, 0.00737, 0.07529, 0.00613, 0.00074, 0.05456, // L
+    0.02968, 0.07095, 0.05796, 0.02521, 0.01362, 0.06693, // R
+    0.07948, 0.07244, 0.06311, 0.01838, 0.00049, 0.00427, // X
+    0.00128, 0.00326, // Z
+];
+
+const GERMAN_LETTER_FREQ: [f32; 26] = [
+    0.06516, 0.01886, 0.02732, 0.05076, 0.16396, 0.01656, // F
+    0.03009, 0.04577, 0.0655, 0.00268, 0.01417, 0.03437, // L
+    0.02534, 0.09776, 0.02594, 0.0067, 0.00018, 0.07003, // R
+    0.0727, 0.06154, 0.04166, 0.00846, 0.01921, 0.00034, // X
+    0.00039, 0.01134, // Z
+];
+
+impl Language {
+    fn letter_frequencies(self) -> &'static [f32; 26] {
+        match self {
+            Language::English => &ENGLISH_LETTER_FREQ,
+            Language::French => &FRENCH_LETTER_FREQ,
+            Language::German => &GERMAN_LETTER_FREQ,
         }
+    }
+}
 
-        let english_letter_stats = [
-            0.082, 0.015, 0.028, 0.043, 0.127, 0.022, // F
-            0.02, 0.061, 0.07, 0.0015, 0.0077, 0.04, // L
-            0.024, 0.067, 0.075, 0.019, 0.00095, 0.06, // R
-            0.063, 0.091, 0.028, 0.0098, 0.024, 0.0015, // X
-            0.02, 0.00074, // Z
-        ];
-        let number_of_letters: i32 = letter_stats.iter().sum();
-        let letter_stats = letter_stats.map(|a| a as f32 / number_of_letters as f32);
-        let ssd = letter_stats
-            .iter()
-            .zip(english_letter_stats.iter())
-            .map(|(a, b)| (a - b).powf(2.0));
-        let ssd = 1.0 - ssd.sum::<f32>();
+/// Scores the data by comparing its letter frequencies with the ones of the language. Accented
+/// letters are not supported, the data must be ASCII.
+pub struct LetterFreqScorer {
+    language: Language,
+}
+
+impl LetterFreqScorer {
+    pub fn new(language: Language) -> LetterFreqScorer {
+        LetterFreqScorer { language }
+    }
+
+    pub fn score(&self, data: &[u8]) -> Option<f32> {
+        let (letter_stats, non_printable) = letter_stats(data);
+        if non_printable > 0 {
+            return None;
+        }
+        let ssd = self.similarity(&letter_stats);
 
         debug!(
-            "[score_english] for {}: ssd {}",
-            String::from_utf8(data.to_vec()).unwrap(),
+            "[score_{:?}] for {}: ssd {}",
+            self.language,
+            to_printable(data),
             ssd
         );
 
         Some(ssd)
     }
+
+    /// Same as `score`, but the bytes that are not printable lower the score (by 2 for data
+    /// made only of them) instead of rejecting the data altogether.
+    pub fn score_tolerant(&self, data: &[u8]) -> f32 {
+        let (letter_stats, non_printable) = letter_stats(data);
+        let ssd = self.similarity(&letter_stats);
+        let penalty = 2.0 * non_printable as f32 / max(1, data.len()) as f32;
+
+        debug!(
+            "[score_tolerant_{:?}] for {}: ssd {} penalty {}",
+            self.language,
+            to_printable(data),
+            ssd,
+            penalty
+        );
+
+        ssd - penalty
+    }
+
+    /// 1 minus the sum of squared differences with the language letter frequencies
+    fn similarity(&self, letter_stats: &[i32; 26]) -> f32 {
+        let number_of_letters: i32 = letter_stats.iter().sum();
+        let letter_stats = letter_stats.map(|a| a as f32 / number_of_letters as f32);
+        let ssd = letter_stats
+            .iter()
+            .zip(self.language.letter_frequencies().iter())
+            .map(|(a, b)| (a - b) * (a - b));
+        1.0 - ssd.sum::<f32>()
+    }
+}
+
+/// Number of occurrences of each letter (case insensitive), and number of non printable bytes
+fn letter_stats(data: &[u8]) -> ([i32; 26], usize) {
+    let mut letter_stats = [0; 26];
+    let mut non_printable = 0;
+    for c in data {
+        if c.is_ascii_alphabetic() {
+            letter_stats[c.to_ascii_lowercase() as usize - 'a' as usize] += 1;
+        } else if !(c.is_ascii_alphanumeric()
+            || c.is_ascii_whitespace()
+            || c.is_ascii_punctuation())
+        {
+            non_printable += 1;
+        }
+    }
+    (letter_stats, non_printable)
+}
+
+pub struct EnglishLetterFreq();
+
+impl ScoringFunction for EnglishLetterFreq {
+    fn score(data: &[u8]) -> Option<f32> {
+        LetterFreqScorer::new(Language::English).score(data)
+    }
+}
+
+/// Average frequency of the characters in English text, spaces included. Unlike
+/// `EnglishLetterFreq` this works on a handful of characters taken from different places of
+/// different texts, such as the columns of a repeating-key XOR.
+pub struct EnglishCharFreq();
+
+impl ScoringFunction for EnglishCharFreq {
+    fn score(data: &[u8]) -> Option<f32> {
+        if data.is_empty() {
+            return None;
+        }
+        let mut total = 0.0;
+        for c in data {
+            total += match c {
+                // About one character out of 5 is a space
+                b' ' => 0.19,
+                b'a'..=b'z' => 0.8 * ENGLISH_LETTER_FREQ[(c - b'a') as usize],
+                // Mostly at the beginning of sentences
+                b'A'..=b'Z' => 0.4 * ENGLISH_LETTER_FREQ[(c - b'A') as usize],
+                _ if c.is_ascii_alphanumeric()
+                    || c.is_ascii_whitespace()
+                    || c.is_ascii_punctuation() =>
+                {
+                    0.002
+                }
+                _ => return None,
+            };
+        }
+        Some(total / data.len() as f32)
+    }
+}
+
+/// `EnglishLetterFreq` for noisy data, see `LetterFreqScorer::score_tolerant`
+pub struct TolerantEnglishLetterFreq();
+
+impl ScoringFunction for TolerantEnglishLetterFreq {
+    fn score(data: &[u8]) -> Option<f32> {
+        Some(LetterFreqScorer::new(Language::English).score_tolerant(data))
+    }
+}
+
+pub struct FrenchLetterFreq();
+
+impl ScoringFunction for FrenchLetterFreq {
+    fn score(data: &[u8]) -> Option<f32> {
+        LetterFreqScorer::new(Language::French).score(data)
+    }
+}
+
+pub struct GermanLetterFreq();
+
+impl ScoringFunction for GermanLetterFreq {
+    fn score(data: &[u8]) -> Option<f32> {
+        LetterFreqScorer::new(Language::German).score(data)
+    }
 }
 
 pub struct EnglishWordFreq();
 
 impl ScoringFunction for EnglishWordFreq {
     fn score(data: &[u8]) -> Option<f32> {
+        // Nothing to score, and the ratios below would be 0/0
+        if data.is_empty() {
+            return None;
+        }
+
         // https://www3.nd.edu/~busiforc/handouts/cryptography/letterfrequencies.html
         // e, a, r, i, o, t makes about 50% of all the letters found in a typical english text
         let mut letters_bucket1 = 0;
@@ -62,10 +230,15 @@ impl ScoringFunction for EnglishWordFreq {
         // the remaining letters make up the other 50%
         let mut letters_bucket2 = 0;
 
+        let mut whitespaces = 0;
+
         for b in data {
             if !(b.is_ascii_alphanumeric() || b.is_ascii_whitespace() || b.is_ascii_punctuation()) {
                 return None;
             }
+            if b.is_ascii_whitespace() {
+                whitespaces += 1;
+            }
             if b.is_ascii_alphabetic() {
                 if [b'e', b'a', b'r', b'i', b'o', b't'].contains(&b.to_ascii_lowercase()) {
                     letters_bucket1 += 1;
@@ -77,18 +250,18 @@ impl ScoringFunction for EnglishWordFreq {
 
         // 0 is perfect, 0.5 is worst
         let frequency_score: f32 =
-            (letters_bucket1 as f32 / (letters_bucket1 + letters_bucket2) as f32 - 0.5).abs();
+            (letters_bucket1 as f32 / max(1, letters_bucket1 + letters_bucket2) as f32 - 0.5).abs();
         // 0 is worst, 1 is perfect
         let frequency_score = 1.0 - 2.0 * frequency_score;
 
-        let words: HashSet<String> = data
+        let words: BTreeSet<String> = data
             .split(|c| c.is_ascii_whitespace() | c.is_ascii_punctuation())
             .map(|word| String::from_utf8(word.to_ascii_lowercase()).unwrap())
             .collect();
 
-        // Most ommon words
-        let common_words: HashSet<String> = HashSet::from_iter(
-            ["the", "to", "of", "and", "a", "in", "that", "have", "I"]
+        // Most common words, lowercase since the words of the data are lowercased
+        let common_words: BTreeSet<String> = BTreeSet::from_iter(
+            ["the", "to", "of", "and", "a", "in", "that", "have", "i"]
                 .iter()
                 .map(|s| s.to_string()),
         );
@@ -98,102 +271,225 @@ impl ScoringFunction for EnglishWordFreq {
 
         let words_length: Vec<usize> = words.iter().map(|w| w.len()).filter(|l| *l > 0).collect();
         let average_word_length =
-            words_length.iter().sum::<usize>() as f32 / words_length.len() as f32;
+            words_length.iter().sum::<usize>() as f32 / max(1, words_length.len()) as f32;
         // Let's pick an average word length of 4.5
         // https://www.researchgate.net/figure/Dynamics-of-average-length-of-short-and-long-words_fig2_230764201
         // 0.0 for an average word length of 9, 1.0 for 4.5.
         // We accept that it is unbounded in the negative for large words because average word length >> 9 chars are highly unlikely
         let average_word_length_score = 1.0 - (average_word_length - 4.5).abs() / 4.5;
 
+        // Penalize printable noise such as hex or base64 strings: English text is mostly letters
+        // (~80% of the non-whitespace characters) and longer sentences always contain spaces.
+        // 0 is perfect, negative otherwise.
+        let letters = letters_bucket1 + letters_bucket2;
+        let symbols = data.len() - whitespaces - letters;
+        let letter_ratio = letters as f32 / max(1, letters + symbols) as f32;
+        let mut noise_score = (letter_ratio - 0.6).min(0.0) * 2.0;
+        if whitespaces == 0 && data.len() > 16 {
+            noise_score -= 1.0;
+        }
+
         debug!(
-            "[score_english] for {}: cm {} fr {} wl {}",
-            String::from_utf8(data.to_vec()).unwrap(),
+            "[score_english] for {}: cm {} fr {} wl {} ns {}",
+            to_printable(data),
             common_word_score,
             frequency_score,
-            average_word_length_score
+            average_word_length_score,
+            noise_score
         );
 
-        Some(common_word_score + frequency_score + average_word_length_score)
+        Some(common_word_score + frequency_score + average_word_length_score + noise_score)
     }
 }
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct DecodingResult {
     pub score: f32,
     pub key: u8,
+    #[cfg_attr(feature = "serde", serde(with = "crate::base64::serde"))]
     pub decoded_content: Vec<u8>,
 }
 
-pub fn break_xor_single_char<T: ScoringFunction>(data: &[u8]) -> Option<DecodingResult> {
-    let mut max_score = f32::MIN;
-    let mut result: Option<DecodingResult> = None;
-
-    for key in 0u8..=255u8 {
-        let decoded: Vec<u8> = data.iter().map(|c| c ^ key).collect();
-        let score = T::score(&decoded);
-
-        if let Some(score) = score {
-            if score > max_score {
-                debug!(
-                    "[decode_xor] Better score: {}: {}",
-                    score,
-                    String::from_utf8(decoded.clone()).unwrap()
-                );
-                result = Some(DecodingResult {
-                    score,
-                    key,
-                    decoded_content: decoded,
-                });
-                max_score = score;
-            }
+impl DecodingResult {
+    /// Comparator for `sort_by`, ordering the results by increasing score. Since the scores are
+    /// `f32`, NaN compares as equal to everything.
+    pub fn by_score(a: &DecodingResult, b: &DecodingResult) -> Ordering {
+        a.score.partial_cmp(&b.score).unwrap_or(Ordering::Equal)
+    }
+
+    /// The first `max` bytes of the decoded content, see `to_printable`
+    pub fn preview(&self, max: usize) -> String {
+        to_printable(&self.decoded_content[..min(max, self.decoded_content.len())])
+    }
+}
+
+/// Results are compared on their score only, so that candidates can be ranked
+impl PartialEq for DecodingResult {
+    fn eq(&self, other: &Self) -> bool {
+        self.score == other.score
+    }
+}
+
+impl PartialOrd for DecodingResult {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        self.score.partial_cmp(&other.score)
+    }
+}
+
+/// Item with the highest score, the first one in case of a tie. Items with a NaN score are
+/// skipped, since they can't be compared.
+pub fn max_by_score<T>(items: impl Iterator<Item = T>, score: impl Fn(&T) -> f32) -> Option<T> {
+    let mut best: Option<(f32, T)> = None;
+    for item in items {
+        let item_score = score(&item);
+        if item_score.is_nan() {
+            continue;
+        }
+        if best
+            .as_ref()
+            .is_none_or(|(best_score, _)| item_score > *best_score)
+        {
+            best = Some((item_score, item));
         }
     }
+    best.map(|(_, item)| item)
+}
+
+pub fn break_xor_single_char<T: ScoringFunction>(data: &[u8]) -> Option<DecodingResult> {
+    let candidates = (0u8..=255u8).filter_map(|key| {
+        let decoded = xor_byte(data, key);
+        T::score(&decoded).map(|score| DecodingResult {
+            score,
+            key,
+            decoded_content: decoded,
+        })
+    });
+    let result = max_by_score(candidates, |candidate| candidate.score);
+    if let Some(result) = &result {
+        debug!(
+            "[decode_xor] Best score: {}: {}",
+            result.score,
+            to_printable(&result.decoded_content)
+        );
+    }
     result
 }
 
-pub fn hamming_distance(block1: &[u8], block2: &[u8]) -> u32 {
+/// Same as `break_xor_single_char`, but only returns the key
+pub fn recover_single_byte_xor_key<T: ScoringFunction>(data: &[u8]) -> Option<u8> {
+    break_xor_single_char::<T>(data).map(|r| r.key)
+}
+
+/// Finds which of the lines is the most likely to be single-byte XOR encrypted english text,
+/// returning its index along with the decryption.
+pub fn detect_single_char_xor<T: ScoringFunction>(
+    lines: &[Vec<u8>],
+) -> Option<(usize, DecodingResult)> {
+    let candidates = lines.iter().enumerate().filter_map(|(index, line)| {
+        break_xor_single_char::<T>(line).map(|decoded| (index, decoded))
+    });
+    max_by_score(candidates, |(_, decoded)| decoded.score)
+}
+
+/// Number of bits set in the data
+pub fn popcount(data: &[u8]) -> u64 {
+    data.iter().map(|c| c.count_ones() as u64).sum()
+}
+
+/// Number of differing bits, the shortest block being padded with zeros
+pub fn hamming_distance(block1: &[u8], block2: &[u8]) -> u64 {
     let (short, long) = if block2.len() > block1.len() {
-        (block1.iter(), block2.iter())
+        (block1, block2)
     } else {
-        (block2.iter(), block1.iter())
+        (block2, block1)
     };
 
-    let mut distance = 0;
-    for (c1, c2) in long.zip(short.chain([0].iter().cycle())) {
-        distance += (c1 ^ c2).count_ones();
+    let common: u64 = short
+        .iter()
+        .zip(long)
+        .map(|(c1, c2)| (c1 ^ c2).count_ones() as u64)
+        .sum();
+    common + popcount(&long[short.len()..])
+}
+
+/// Hamming distance per byte of the longest block, 0 if both blocks are empty
+pub fn normalized_hamming(block1: &[u8], block2: &[u8]) -> f64 {
+    let len = max(block1.len(), block2.len());
+    if len == 0 {
+        return 0.0;
     }
-    distance
+    hamming_distance(block1, block2) as f64 / len as f64
+}
+
+/// Smallest key size considered by `find_likely_xor_keysizes`
+const MIN_XOR_KEY_SIZE: usize = 2;
+/// Largest key size considered by default
+const MAX_XOR_KEY_SIZE: usize = 40;
+/// Number of full blocks compared with each other to score a key size
+const KEYSIZE_SAMPLE_BLOCKS: usize = 3;
+
+/// Yields each candidate key size with its score, the average normalized hamming distance
+/// between the first 3 blocks of that size (lower is more likely). Only the key sizes for which
+/// there is enough data to compare 3 full blocks are considered, so nothing is yielded when the
+/// data is too short to give a meaningful answer.
+pub fn keysize_scores(data: &[u8]) -> impl Iterator<Item = (usize, f64)> + '_ {
+    keysize_scores_in(data, MIN_XOR_KEY_SIZE..=MAX_XOR_KEY_SIZE)
+}
+
+/// Same as `keysize_scores` for the key sizes in `range`, clamped to the valid sizes
+pub fn keysize_scores_in(
+    data: &[u8],
+    range: RangeInclusive<usize>,
+) -> impl Iterator<Item = (usize, f64)> + '_ {
+    let min_key_size = max(MIN_XOR_KEY_SIZE, *range.start());
+    let max_key_size = min(*range.end(), data.len() / KEYSIZE_SAMPLE_BLOCKS);
+
+    (min_key_size..=max_key_size).map(|key_size| {
+        let mut blocks = data.chunks_exact(key_size);
+        // There are at least 3 blocks by construction of max_key_size
+        let block1 = blocks.next().unwrap();
+        let block2 = blocks.next().unwrap();
+        let block3 = blocks.next().unwrap();
+        let mut score = normalized_hamming(block1, block2);
+        score += normalized_hamming(block1, block3);
+        score += normalized_hamming(block2, block3);
+        score /= 3.0;
+        debug!("[find_xor_keysize] size: {:?}, score {:?}", key_size, score);
+        (key_size, score)
+    })
+}
+
+/// Most likely key size, `None` if the data is too short
+pub fn find_xor_keysize(data: &[u8]) -> Option<usize> {
+    find_xor_keysize_in(data, MIN_XOR_KEY_SIZE..=MAX_XOR_KEY_SIZE)
+}
+
+/// Most likely key size in `range`, see `keysize_scores_in`
+pub fn find_xor_keysize_in(data: &[u8], range: RangeInclusive<usize>) -> Option<usize> {
+    max_by_score(keysize_scores_in(data, range), |(_, score)| -*score as f32)
+        .map(|(key_size, _)| key_size)
 }
 
+/// Returns the candidate key sizes of `keysize_scores`, most likely first. Key sizes with a NaN
+/// score can't be ranked and are left out. Empty when less than two key sizes can be compared,
+/// a single candidate would be returned whatever the real key size.
 pub fn find_likely_xor_keysizes(data: &[u8]) -> Vec<usize> {
-    let max_key_size = min(40, data.len() / 3);
-
-    let mut scores: Vec<(usize, f32)> = (2..=max_key_size)
-        .map(|key_size| {
-            let iter = data.iter();
-            let block1 = iter.clone().take(key_size).copied().collect::<Vec<u8>>();
-            let block2 = iter
-                .clone()
-                .skip(key_size)
-                .take(key_size)
-                .copied()
-                .collect::<Vec<u8>>();
-            let block3 = iter
-                .skip(2 * key_size)
-                .take(key_size)
-                .copied()
-                .collect::<Vec<u8>>();
-            let mut score = hamming_distance(&block1, &block2) as f32 / key_size as f32;
-            score += hamming_distance(&block1, &block3) as f32 / key_size as f32;
-            score += hamming_distance(&block2, &block3) as f32 / key_size as f32;
-            score /= 3.0;
-            debug!("[find_xor_keysize] size: {:?}, score {:?}", key_size, score);
-            (key_size, score)
-        })
+    let mut scores: Vec<(usize, f64)> = keysize_scores(data)
+        .filter(|(_, score)| !score.is_nan())
         .collect();
-    scores.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(Ordering::Equal));
+    if scores.len() < 2 {
+        return vec![];
+    }
+    // Stable, the smallest key size comes first in case of a tie
+    scores.sort_by(|a, b| a.1.total_cmp(&b.1));
     scores.iter().map(|s| s.0).collect()
 }
 
+/// Returns `key_size` columns, empty if `key_size` is 0
 fn transpose_blocks(data: &[u8], key_size: usize) -> Vec<Vec<u8>> {
+    if key_size == 0 {
+        return vec![];
+    }
     let mut blocks = Vec::new();
     blocks.resize_with(key_size, Vec::new);
     data.chunks(key_size)
@@ -201,17 +497,132 @@ fn transpose_blocks(data: &[u8], key_size: usize) -> Vec<Vec<u8>> {
     blocks
 }
 
+/// Same as `transpose_blocks` with the columns stored one after the other in a single buffer,
+/// which avoids an allocation per column. Returns the buffer and the length of each column.
+pub fn transpose_flat(data: &[u8], key_size: usize) -> (Vec<u8>, Vec<usize>) {
+    if key_size == 0 {
+        return (vec![], vec![]);
+    }
+    let mut columns = Vec::with_capacity(data.len());
+    let mut lengths = Vec::with_capacity(key_size);
+    for column in 0..key_size {
+        let before = columns.len();
+        columns.extend(data.iter().skip(column).step_by(key_size));
+        lengths.push(columns.len() - before);
+    }
+    (columns, lengths)
+}
+
 pub fn find_key_block_xor(data: &[u8], key_size: usize) -> Option<Vec<u8>> {
+    find_key_block_xor_with::<EnglishLetterFreq>(data, key_size)
+}
+
+/// Same as `find_key_block_xor`, with the scoring function used on each column of the data
+pub fn find_key_block_xor_with<T: ScoringFunction>(
+    data: &[u8],
+    key_size: usize,
+) -> Option<Vec<u8>> {
+    find_key_block_xor_scored_with::<T>(data, key_size)
+        .map(|key| key.into_iter().map(|(key_byte, _)| key_byte).collect())
+}
+
+/// Same as `find_key_block_xor`, also returning the score of each key byte: a column with a
+/// much lower score than the others is likely to be wrong.
+pub fn find_key_block_xor_scored(data: &[u8], key_size: usize) -> Option<Vec<(u8, f32)>> {
+    find_key_block_xor_scored_with::<EnglishLetterFreq>(data, key_size)
+}
+
+fn find_key_block_xor_scored_with<T: ScoringFunction>(
+    data: &[u8],
+    key_size: usize,
+) -> Option<Vec<(u8, f32)>> {
+    if key_size == 0 {
+        return None;
+    }
     transpose_blocks(data, key_size)
         .iter()
-        .map(|b| break_xor_single_char::<EnglishLetterFreq>(b))
-        .map(|d| d.map(|d| d.key))
-        .collect::<Option<Vec<u8>>>()
+        .map(|b| break_xor_single_char::<T>(b).map(|result| (result.key, result.score)))
+        .collect()
+}
+
+/// Recovers the bytes of a repeating XOR key of size `key_size` from a known part of the
+/// plaintext (`crib`) found at `position`. The key bytes not covered by the crib are `None`.
+/// When the crib is longer than the key, the first occurrence of each key byte is kept.
+pub fn recover_key_from_crib(
+    ciphertext: &[u8],
+    crib: &[u8],
+    position: usize,
+    key_size: usize,
+) -> Vec<Option<u8>> {
+    let mut key = vec![None; key_size];
+    if key_size == 0 {
+        return key;
+    }
+
+    for (index, (c, p)) in ciphertext.iter().skip(position).zip(crib).enumerate() {
+        key[(position + index) % key_size].get_or_insert(c ^ p);
+    }
+    key
 }
 
 #[cfg(test)]
 mod tests {
     use crate::decrypt::*;
+    use crate::encrypt::encode_xor;
+
+    #[test]
+    fn test_to_printable() {
+        assert_eq!(to_printable(b""), "");
+        assert_eq!(to_printable(b"Hello, world!"), "Hello, world!");
+        assert_eq!(
+            to_printable(b"Hello\0\n\tworld\xff\xc3\xa9~"),
+            "Hello...world...~"
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_decoding_result_serde() {
+        use serde_test::{assert_de_tokens_error, assert_tokens, Token};
+
+        let result = DecodingResult {
+            score: 0.5,
+            key: 42,
+            decoded_content: b"Hello, world!".to_vec(),
+        };
+        let tokens = |content| {
+            [
+                Token::Struct {
+                    name: "DecodingResult",
+                    len: 3,
+                },
+                Token::Str("score"),
+                Token::F32(0.5),
+                Token::Str("key"),
+                Token::U8(42),
+                Token::Str("decoded_content"),
+                Token::Str(content),
+                Token::StructEnd,
+            ]
+        };
+        assert_tokens(&result, &tokens("SGVsbG8sIHdvcmxkIQ=="));
+        assert_de_tokens_error::<DecodingResult>(
+            &tokens("SGV!")[..7],
+            "Invalid Base64 data, char ! at position 3 is invalid",
+        );
+    }
+
+    #[test]
+    fn test_decoding_result_preview() {
+        let result = DecodingResult {
+            score: 1.0,
+            key: 0,
+            decoded_content: b"Cook\x00\xffing MC's like a pound of bacon".to_vec(),
+        };
+        assert_eq!(result.preview(10), "Cook..ing ");
+        assert_eq!(result.preview(0), "");
+        assert_eq!(result.preview(1000), "Cook..ing MC's like a pound of bacon");
+    }
 
     #[test]
     fn test_englishwordfreq() {
@@ -228,6 +639,72 @@ mod tests {
                 > EnglishWordFreq::score(b"CCCvdd jdsdsdg suy yes of DDDDNNN").unwrap()
         );
         assert!(EnglishWordFreq::score(b"Hello\0world").is_none());
+
+        // 'i' and 'o' are in the same frequency bucket, only the common word differs
+        assert!(
+            EnglishWordFreq::score(b"I think I know what I want").unwrap()
+                > EnglishWordFreq::score(b"O think O know what O want").unwrap()
+        );
+        assert_eq!(
+            EnglishWordFreq::score(b"I think I know what I want"),
+            EnglishWordFreq::score(b"i think i know what i want")
+        );
+    }
+
+    #[test]
+    fn test_englishwordfreq_empty() {
+        assert!(EnglishWordFreq::score(b"").is_none());
+        assert!(break_xor_single_char::<EnglishWordFreq>(b"").is_none());
+
+        // No letters or no words at all, the score must still be a number
+        for data in [&b"   "[..], b"123 456", b"...", b"\n"] {
+            assert!(!EnglishWordFreq::score(data).unwrap().is_nan());
+        }
+    }
+
+    #[test]
+    fn test_englishwordfreq_noise() {
+        let english = EnglishWordFreq::score(b"Now that the party is jumping").unwrap();
+        assert!(english > EnglishWordFreq::score(b"4A6F686E20536D6974680A").unwrap());
+        assert!(english > EnglishWordFreq::score(b"SGVsbG8sIHdvcmxkIQ==").unwrap());
+        assert!(english > EnglishWordFreq::score(b"12 34, 56! 78 90; 1a 2b").unwrap());
+    }
+
+    #[test]
+    fn test_tolerant_englishletterfreq() {
+        let noisy = b"Hello world, This is a weird test\x07";
+        let gibberish: Vec<u8> = (0..34).map(|i: u8| i.wrapping_mul(97) ^ 0xc3).collect();
+        assert!(EnglishLetterFreq::score(noisy).is_none());
+
+        let noisy_score = TolerantEnglishLetterFreq::score(noisy).unwrap();
+        assert!(noisy_score > TolerantEnglishLetterFreq::score(&gibberish).unwrap());
+        assert!(noisy_score < TolerantEnglishLetterFreq::score(&noisy[..33]).unwrap());
+        assert_eq!(
+            TolerantEnglishLetterFreq::score(&noisy[..33]),
+            EnglishLetterFreq::score(&noisy[..33])
+        );
+    }
+
+    #[test]
+    fn test_letterfreqscorer() {
+        let french = b"Ce que je veux, je le veux vraiment";
+        let ciphertext: Vec<u8> = french.iter().map(|c| c ^ 0x5a).collect();
+
+        let english_result = break_xor_single_char::<EnglishLetterFreq>(&ciphertext).unwrap();
+        assert_ne!(english_result.key, 0x5a);
+
+        let french_result = break_xor_single_char::<FrenchLetterFreq>(&ciphertext).unwrap();
+        assert_eq!(french_result.key, 0x5a);
+        assert_eq!(french_result.decoded_content, french);
+
+        let german = b"Der schnelle braune Fuchs springt";
+        assert!(
+            GermanLetterFreq::score(german).unwrap() > EnglishLetterFreq::score(german).unwrap()
+        );
+        assert_eq!(
+            LetterFreqScorer::new(Language::English).score(b"Hello world"),
+            EnglishLetterFreq::score(b"Hello world")
+        );
     }
 
     #[test]
@@ -275,6 +752,74 @@ mod tests {
     );
     }
 
+    #[test]
+    fn test_decoding_result_ordering() {
+        let result = |score, key| DecodingResult {
+            score,
+            key,
+            decoded_content: vec![key],
+        };
+        let mut results: Vec<DecodingResult> = [(0.5, 1), (-1.0, 2), (2.0, 3), (0.1, 4)]
+            .into_iter()
+            .map(|(score, key)| result(score, key))
+            .collect();
+        results.sort_by(DecodingResult::by_score);
+        assert_eq!(
+            results.iter().map(|r| r.key).collect::<Vec<u8>>(),
+            vec![2, 4, 1, 3]
+        );
+
+        assert!(result(1.0, 1) > result(0.5, 2));
+        assert_eq!(result(1.0, 1), result(1.0, 2));
+        assert_eq!(result(1.0, 1).clone().decoded_content, vec![1]);
+    }
+
+    #[test]
+    fn test_recover_single_byte_xor_key() {
+        let encrypted: Vec<u8> = b"They are endowed with reason and conscience"
+            .iter()
+            .map(|c| c ^ 0x5a)
+            .collect();
+        assert_eq!(
+            recover_single_byte_xor_key::<EnglishWordFreq>(&encrypted),
+            Some(0x5a)
+        );
+        assert_eq!(
+            recover_single_byte_xor_key::<EnglishWordFreq>(&encrypted),
+            break_xor_single_char::<EnglishWordFreq>(&encrypted).map(|r| r.key)
+        );
+        assert_eq!(
+            recover_single_byte_xor_key::<EnglishWordFreq>(&(0..255).collect::<Vec<u8>>()),
+            None
+        );
+    }
+
+    #[test]
+    fn test_max_by_score() {
+        let items = [1.0, f32::NAN, 3.0, 2.0, 3.0];
+        assert_eq!(
+            max_by_score(items.iter().enumerate(), |(_, s)| **s),
+            Some((2, &3.0))
+        );
+        // NaN is skipped, not selected
+        assert_eq!(max_by_score([f32::NAN, -1.0].iter(), |s| **s), Some(&-1.0));
+        assert_eq!(max_by_score([f32::NAN].iter(), |s| **s), None);
+        assert_eq!(max_by_score(std::iter::empty::<f32>(), |s| *s), None);
+
+        let words = ["a", "bb", "ccc"];
+        let nan_for_long_words = |w: &&str| {
+            if w.len() > 2 {
+                f32::NAN
+            } else {
+                w.len() as f32
+            }
+        };
+        assert_eq!(
+            max_by_score(words.into_iter(), nan_for_long_words),
+            Some("bb")
+        );
+    }
+
     #[test]
     fn test_decode_xor_failure_englishwordfreq() {
         let encrypted: Vec<u8> = (0..255).collect();
@@ -282,6 +827,80 @@ mod tests {
         assert!(decrypted.is_none());
     }
 
+    #[test]
+    fn test_detect_single_char_xor() {
+        let plaintext = b"They are endowed with reason and conscience";
+        let lines = vec![
+            (0..43).collect::<Vec<u8>>(),
+            plaintext.iter().map(|c| c ^ 0x42).collect(),
+            (0..43).map(|i: u8| i.wrapping_mul(97) ^ 0xc3).collect(),
+        ];
+
+        let (index, decoded) = detect_single_char_xor::<EnglishWordFreq>(&lines).unwrap();
+        assert_eq!(index, 1);
+        assert_eq!(decoded.key, 0x42);
+        assert_eq!(decoded.decoded_content, plaintext);
+
+        let (index, decoded) = detect_single_char_xor::<EnglishLetterFreq>(&lines).unwrap();
+        assert_eq!(index, 1);
+        assert_eq!(decoded.key, 0x42);
+
+        assert!(detect_single_char_xor::<EnglishWordFreq>(&[]).is_none());
+    }
+
+    #[test]
+    fn test_recover_key_from_crib() {
+        let key = b"SECRET";
+        let ciphertext =
+            crate::encrypt::encode_xor(b"Attack at dawn, bring the ladders", key).unwrap();
+
+        // "at dawn" covers positions 7 to 13, so all of the key
+        assert_eq!(
+            recover_key_from_crib(&ciphertext, b"at dawn", 7, 6),
+            key.map(Some).to_vec()
+        );
+        // "bring" covers positions 16 to 20, so the key bytes 4, 5, 0, 1 and 2
+        assert_eq!(
+            recover_key_from_crib(&ciphertext, b"bring", 16, 6),
+            vec![
+                Some(b'S'),
+                Some(b'E'),
+                Some(b'C'),
+                None,
+                Some(b'E'),
+                Some(b'T')
+            ]
+        );
+        // The crib is cut at the end of the ciphertext
+        assert_eq!(
+            recover_key_from_crib(&ciphertext, b"ladders and more", 26, 6),
+            vec![
+                Some(b'S'),
+                Some(b'E'),
+                Some(b'C'),
+                Some(b'R'),
+                Some(b'E'),
+                Some(b'T')
+            ]
+        );
+        assert_eq!(
+            recover_key_from_crib(&ciphertext, b"at", 100, 6),
+            vec![None; 6]
+        );
+        assert!(recover_key_from_crib(&ciphertext, b"at", 7, 0).is_empty());
+    }
+
+    #[test]
+    fn test_popcount() {
+        assert_eq!(popcount(&[0xff, 0x0f]), 12);
+        assert_eq!(popcount(&[]), 0);
+        assert_eq!(popcount(&[0; 10]), 0);
+        assert_eq!(
+            popcount(b"this is a test"),
+            hamming_distance(b"this is a test", b"")
+        );
+    }
+
     #[test]
     fn test_hamming_distance() {
         assert_eq!(hamming_distance(b"this is a test", b"this is a test"), 0);
@@ -291,9 +910,26 @@ mod tests {
         assert_eq!(hamming_distance(&[0b1111, 0b11], &[]), 6);
     }
 
+    #[test]
+    fn test_normalized_hamming() {
+        assert_eq!(
+            normalized_hamming(b"this is a test", b"wokka wokka!!!"),
+            37.0 / 14.0
+        );
+        assert_eq!(normalized_hamming(&[0xff, 0], &[0, 0]), 4.0);
+        assert_eq!(normalized_hamming(&[0b1111, 0b11], &[]), 3.0);
+        assert_eq!(normalized_hamming(&[], &[]), 0.0);
+    }
+
     #[test]
     fn test_find_likely_xor_keysizes() {
         assert_eq!(find_likely_xor_keysizes(&[]), vec![]);
+        // Not enough data for 3 blocks of the smallest key size
+        assert_eq!(find_likely_xor_keysizes(&[0, 1, 2]), vec![]);
+        assert_eq!(find_likely_xor_keysizes(&[0, 1, 2, 0, 1]), vec![]);
+        // Only the smallest key size can be checked, the real one is 3
+        assert_eq!(find_likely_xor_keysizes(&[0, 1, 2, 0, 1, 2]), vec![]);
+        assert_eq!(find_likely_xor_keysizes(&[0, 1, 2, 0, 1, 2, 0, 1]), vec![]);
         assert_eq!(
             find_likely_xor_keysizes(&[0, 1, 2, 0, 1, 2, 0, 1, 2]),
             vec![3, 2]
@@ -308,6 +944,124 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_keysize_scores() {
+        assert_eq!(keysize_scores(&[0, 1, 2, 0, 1]).count(), 0);
+
+        let data = encode_xor(
+            b"Burning 'em, if you ain't quick and nimble I go crazy when I hear a cymbal",
+            b"ICE",
+        )
+        .unwrap();
+        let scores: Vec<(usize, f64)> = keysize_scores(&data).collect();
+        assert_eq!(scores.len(), min(40, data.len() / 3) - 1);
+        assert_eq!(scores[0].0, 2);
+
+        let best = max_by_score(scores.iter(), |(_, score)| -*score as f32).unwrap();
+        assert_eq!(best.0, find_likely_xor_keysizes(&data)[0]);
+    }
+
+    #[test]
+    fn test_find_xor_keysize_in() {
+        let data = encode_xor(
+            b"Burning 'em, if you ain't quick and nimble I go crazy when I hear a cymbal",
+            b"ICE",
+        )
+        .unwrap();
+        assert_eq!(
+            find_xor_keysize(&data),
+            Some(find_likely_xor_keysizes(&data)[0])
+        );
+
+        let key_size = find_xor_keysize_in(&data, 10..=20).unwrap();
+        assert!((10..=20).contains(&key_size));
+        assert_eq!(find_xor_keysize_in(&data, 3..=3), Some(3));
+
+        // Clamped to the valid sizes
+        assert_eq!(find_xor_keysize_in(&data, 0..=2), Some(2));
+        let key_size = find_xor_keysize_in(&data, 20..=1000).unwrap();
+        assert!((20..=data.len() / 3).contains(&key_size));
+        assert_eq!(find_xor_keysize_in(&data, 30..=1000), None);
+        assert_eq!(find_xor_keysize_in(&data, 0..=1), None);
+        assert_eq!(find_xor_keysize(&[0, 1, 2]), None);
+    }
+
+    #[test]
+    fn test_find_key_block_xor_scored() {
+        let mut plaintext =
+            b"Burning 'em, if you ain't quick and nimble, I go crazy when I hear a \
+            cymbal and a high hat with a souped up tempo. I'm on a roll, it's time to go solo."
+                .to_vec();
+        let data = encode_xor(&plaintext, b"ICE").unwrap();
+        let scored = find_key_block_xor_scored(&data, 3).unwrap();
+        assert_eq!(
+            scored
+                .iter()
+                .map(|(key_byte, _)| *key_byte)
+                .collect::<Vec<u8>>(),
+            find_key_block_xor(&data, 3).unwrap()
+        );
+
+        // The second column only holds the same letter, any letter would do
+        plaintext
+            .iter_mut()
+            .skip(1)
+            .step_by(3)
+            .for_each(|c| *c = b'q');
+        let data = encode_xor(&plaintext, b"ICE").unwrap();
+        let scored = find_key_block_xor_scored(&data, 3).unwrap();
+        assert_eq!(scored[0].0, b'I');
+        assert_eq!(scored[2].0, b'E');
+        assert!(scored[1].1 < 0.5 * scored[0].1.min(scored[2].1));
+
+        assert_eq!(find_key_block_xor_scored(&data, 0), None);
+    }
+
+    #[test]
+    fn test_transpose_flat() {
+        assert_eq!(
+            transpose_flat(&[1, 2, 3, 4, 5, 6, 7, 8, 9, 10], 3),
+            (vec![1, 4, 7, 10, 2, 5, 8, 3, 6, 9], vec![4, 3, 3])
+        );
+        assert_eq!(transpose_flat(&[1], 3), (vec![1], vec![1, 0, 0]));
+        assert_eq!(transpose_flat(&[1, 2, 3], 0), (vec![], vec![]));
+
+        let data: Vec<u8> = (0..1000u32).map(|i| (i * 7 % 251) as u8).collect();
+        for key_size in [1, 2, 3, 7, 29, 40, 999, 1000, 1001] {
+            let blocks = transpose_blocks(&data, key_size);
+            let (flat, lengths) = transpose_flat(&data, key_size);
+
+            let mut rest = flat.as_slice();
+            for (block, len) in blocks.iter().zip(&lengths) {
+                let (column, tail) = rest.split_at(*len);
+                assert_eq!(block, column);
+                rest = tail;
+            }
+            assert_eq!(lengths.len(), blocks.len());
+            assert!(rest.is_empty());
+        }
+    }
+
+    #[test]
+    #[ignore = "benchmark, run with `cargo test --release -- --ignored --nocapture`"]
+    fn bench_transpose_flat() {
+        let data: Vec<u8> = (0..1_000_000u32).map(|i| (i * 7 % 251) as u8).collect();
+        for key_size in [2, 29, 40, 1000, 100_000] {
+            let start = std::time::Instant::now();
+            for _ in 0..10 {
+                std::hint::black_box(transpose_blocks(&data, key_size));
+            }
+            let nested = start.elapsed() / 10;
+
+            let start = std::time::Instant::now();
+            for _ in 0..10 {
+                std::hint::black_box(transpose_flat(&data, key_size));
+            }
+            let flat = start.elapsed() / 10;
+            println!("key size {key_size}: nested {nested:?}, flat {flat:?}");
+        }
+    }
+
     #[test]
     fn test_transpose() {
         assert_eq!(
@@ -324,5 +1078,19 @@ mod tests {
             vec![vec![1, 4], vec![2], vec![3]]
         );
         assert_eq!(transpose_blocks(&[1], 3), vec![vec![1], vec![], vec![]]);
+        assert!(transpose_blocks(&[1, 2, 3], 0).is_empty());
+        assert!(transpose_blocks(&[], 0).is_empty());
+    }
+
+    #[test]
+    fn test_find_key_block_xor() {
+        let data = crate::encrypt::encode_xor(
+            b"All human beings are born free and equal in dignity and rights",
+            b"KEY",
+        )
+        .unwrap();
+        assert_eq!(find_key_block_xor(&data, 3), Some(b"KEY".to_vec()));
+        assert_eq!(find_key_block_xor(&data, 0), None);
+        assert_eq!(find_key_block_xor(&[], 0), None);
     }
 }
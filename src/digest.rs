@@ -0,0 +1,23 @@
+use alloc::vec::Vec;
+
+/// Common interface of the hash functions, so that constructions such as HMAC can be written
+/// once for all of them.
+pub trait Digest {
+    /// Size of the digest in bytes
+    const OUTPUT_LEN: usize;
+    /// Size of the blocks processed by the compression function, in bytes
+    const BLOCK_LEN: usize;
+
+    fn new() -> Self;
+    fn update(&mut self, data: &[u8]);
+    fn finalize(self) -> Vec<u8>;
+
+    fn digest(data: &[u8]) -> Vec<u8>
+    where
+        Self: Sized,
+    {
+        let mut hasher = Self::new();
+        hasher.update(data);
+        hasher.finalize()
+    }
+}
@@ -1,6 +1,29 @@
+use std::{
+    error::Error,
+    fmt,
+    io::{self, ErrorKind, Read, Write},
+};
+
+use crate::hex::{from_hex, to_hex};
+use crate::BoxResult;
+
 #[derive(Debug, PartialEq)]
 pub struct EmptyKeyError();
 
+impl fmt::Display for EmptyKeyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "The XOR key is empty")
+    }
+}
+
+impl Error for EmptyKeyError {}
+
+#[derive(Debug, PartialEq)]
+pub enum StrictXorError {
+    EmptyKey,
+    EmptyData,
+}
+
 pub fn encode_xor(data: &[u8], key: &[u8]) -> Result<Vec<u8>, EmptyKeyError> {
     if key.is_empty() {
         Err(EmptyKeyError())
@@ -9,11 +32,69 @@ pub fn encode_xor(data: &[u8], key: &[u8]) -> Result<Vec<u8>, EmptyKeyError> {
             .iter()
             .zip(key.iter().cycle())
             .map(|(b, k)| b ^ k)
-            .take(data.len())
             .collect())
     }
 }
 
+/// `encode_xor` on hex strings, the result is in lowercase hex
+pub fn repeating_xor_hex(data_hex: &str, key_hex: &str) -> BoxResult<String> {
+    let encoded = encode_xor(&from_hex(data_hex)?, &from_hex(key_hex)?)?;
+    Ok(to_hex(&encoded))
+}
+
+/// Same as `encode_xor`, for the first `n` bytes only. The rest of the data is copied as is.
+pub fn encode_xor_n(data: &[u8], key: &[u8], n: usize) -> Result<Vec<u8>, EmptyKeyError> {
+    let (head, tail) = data.split_at(n.min(data.len()));
+    Ok([encode_xor(head, key)?.as_slice(), tail].concat())
+}
+
+/// Same as `encode_xor`, but also treats empty data as an error, for callers
+/// where "nothing to encode" is a bug. The key is checked first.
+pub fn encode_xor_strict(data: &[u8], key: &[u8]) -> Result<Vec<u8>, StrictXorError> {
+    if key.is_empty() {
+        Err(StrictXorError::EmptyKey)
+    } else if data.is_empty() {
+        Err(StrictXorError::EmptyData)
+    } else {
+        encode_xor(data, key).map_err(|_| StrictXorError::EmptyKey)
+    }
+}
+
+/// Streaming version of `encode_xor`, from `input` to `output`. The position in the key is
+/// carried over between reads, so the result does not depend on how the input is chunked.
+pub fn encode_xor_stream<R: Read, W: Write>(
+    mut input: R,
+    mut output: W,
+    key: &[u8],
+) -> io::Result<()> {
+    if key.is_empty() {
+        return Err(io::Error::new(ErrorKind::InvalidInput, "Empty XOR key"));
+    }
+
+    let mut buffer = [0u8; 4096];
+    let mut key_offset = 0;
+    loop {
+        let read = match input.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        let rotated_key: Vec<u8> = key
+            .iter()
+            .cycle()
+            .skip(key_offset)
+            .take(key.len())
+            .copied()
+            .collect();
+        // The key can't be empty at this point
+        let encoded = encode_xor(&buffer[..read], &rotated_key).unwrap();
+        output.write_all(&encoded)?;
+        key_offset = (key_offset + read) % key.len();
+    }
+    output.flush()
+}
+
 #[cfg(test)]
 mod tests {
     use crate::encrypt::*;
@@ -27,5 +108,93 @@ mod tests {
         assert_eq!(encode_xor(b"to", b"entitled"), Ok(vec![17, 1]));
         assert_eq!(encode_xor(b"test", b""), Err(EmptyKeyError()));
         assert_eq!(encode_xor(b"", b"test"), Ok(vec![]));
+        assert_eq!(encode_xor(b"", b""), Err(EmptyKeyError()));
+    }
+
+    #[test]
+    fn test_repeating_xor_hex() {
+        let data_hex = to_hex(b"Hello, world!");
+        let encoded = repeating_xor_hex(&data_hex, "0x2000").unwrap();
+        assert_eq!(
+            encoded,
+            to_hex(&encode_xor(b"Hello, world!", &[0x20, 0]).unwrap())
+        );
+        assert_eq!(
+            repeating_xor_hex(&to_hex(b"hello"), "2000").unwrap(),
+            to_hex(b"HeLlO")
+        );
+        assert_eq!(repeating_xor_hex(&encoded, "2000").unwrap(), data_hex);
+        assert_eq!(repeating_xor_hex("", "20").unwrap(), "");
+
+        let error = repeating_xor_hex(&data_hex, "").unwrap_err();
+        assert!(error.is::<EmptyKeyError>());
+        assert!(repeating_xor_hex("zz", "20").is_err());
+        assert!(repeating_xor_hex(&data_hex, "2").is_err());
+    }
+
+    #[test]
+    fn test_encode_xor_n() {
+        let full = encode_xor(b"Everyone", b"is").unwrap();
+        assert_eq!(encode_xor_n(b"Everyone", b"is", 3).unwrap()[..3], full[..3]);
+        assert_eq!(encode_xor_n(b"Everyone", b"is", 3).unwrap()[3..], *b"ryone");
+        assert_eq!(encode_xor_n(b"Everyone", b"is", 0).unwrap(), b"Everyone");
+        assert_eq!(encode_xor_n(b"Everyone", b"is", 100).unwrap(), full);
+        assert_eq!(encode_xor_n(b"Everyone", b"", 3), Err(EmptyKeyError()));
+    }
+
+    #[test]
+    fn test_encode_xor_strict() {
+        assert_eq!(
+            encode_xor_strict(b"Everyone", b"is"),
+            Ok(vec![44, 5, 12, 1, 16, 28, 7, 22])
+        );
+        assert_eq!(
+            encode_xor_strict(b"test", b""),
+            Err(StrictXorError::EmptyKey)
+        );
+        assert_eq!(
+            encode_xor_strict(b"", b"test"),
+            Err(StrictXorError::EmptyData)
+        );
+        assert_eq!(encode_xor_strict(b"", b""), Err(StrictXorError::EmptyKey));
+    }
+
+    /// Returns at most `chunk_size` bytes per read, to exercise the read boundaries
+    struct ChunkedReader<'a> {
+        data: &'a [u8],
+        chunk_size: usize,
+    }
+
+    impl Read for ChunkedReader<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let size = self.chunk_size.min(buf.len()).min(self.data.len());
+            buf[..size].copy_from_slice(&self.data[..size]);
+            self.data = &self.data[size..];
+            Ok(size)
+        }
+    }
+
+    #[test]
+    fn test_encode_xor_stream() {
+        let data = b"Burning 'em, if you ain't quick and nimble\nI go crazy when I hear a cymbal";
+        let expected = encode_xor(data, b"ICE").unwrap();
+
+        for chunk_size in [1, 2, 3, 7, 100] {
+            let mut output = vec![];
+            let reader = ChunkedReader { data, chunk_size };
+            encode_xor_stream(reader, &mut output, b"ICE").unwrap();
+            assert_eq!(output, expected);
+        }
+
+        let mut output = vec![];
+        encode_xor_stream(&b""[..], &mut output, b"ICE").unwrap();
+        assert!(output.is_empty());
+
+        assert_eq!(
+            encode_xor_stream(&data[..], &mut output, b"")
+                .unwrap_err()
+                .kind(),
+            ErrorKind::InvalidInput
+        );
     }
 }
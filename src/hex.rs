@@ -1,7 +1,31 @@
+use alloc::{
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::{error::Error, fmt, fmt::Write};
+
+#[cfg(feature = "std")]
+use crate::base64::data_dir;
+use crate::base64::{from_base64, to_base64};
+use crate::BoxResult;
+
 #[derive(Debug, PartialEq)]
 pub struct ParseError(String);
 
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Error for ParseError {}
+
+/// Decodes a hex string, with an optional `0x` or `0X` prefix
 pub fn from_hex(hex_string: &str) -> Result<Vec<u8>, ParseError> {
+    let hex_string = hex_string
+        .strip_prefix("0x")
+        .or_else(|| hex_string.strip_prefix("0X"))
+        .unwrap_or(hex_string);
     hex_string
         .chars()
         .collect::<Vec<char>>()
@@ -18,6 +42,37 @@ pub fn from_hex(hex_string: &str) -> Result<Vec<u8>, ParseError> {
         .collect::<Result<Vec<u8>, ParseError>>()
 }
 
+/// Loads a challenge data file holding one hex string per line, see `load_base64_file`
+#[cfg(feature = "std")]
+pub fn load_hex_lines(file_id: &str) -> BoxResult<Vec<Vec<u8>>> {
+    let file_name = data_dir().join(file_id).with_extension("txt");
+    std::fs::read_to_string(file_name)?
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(|line| Ok(from_hex(line)?))
+        .collect()
+}
+
+/// Lowercase hex representation of the data
+pub fn to_hex(data: &[u8]) -> String {
+    let mut hex_string = String::with_capacity(2 * data.len());
+    for byte in data {
+        // Writing to a String never fails
+        write!(hex_string, "{:02x}", byte).unwrap();
+    }
+    hex_string
+}
+
+/// Set 1 challenge 1
+pub fn hex_to_base64(hex_string: &str) -> BoxResult<String> {
+    Ok(to_base64(&from_hex(hex_string)?))
+}
+
+/// Inverse of `hex_to_base64`, the hex string is lowercase
+pub fn base64_to_hex(base64_string: &str) -> BoxResult<String> {
+    Ok(to_hex(&from_base64(base64_string)?))
+}
+
 #[cfg(test)]
 mod tests {
     use crate::hex::*;
@@ -37,4 +92,48 @@ mod tests {
         assert!(from_hex("48656c6c6f2c20776f726c642").is_err());
         assert!(from_hex("48656c6c6f2c20776f726c642g").is_err());
     }
+
+    #[test]
+    fn test_from_hex_prefix() {
+        assert_eq!(from_hex("0x48").unwrap(), [0x48]);
+        assert_eq!(from_hex("0X0a").unwrap(), [0x0a]);
+        assert_eq!(from_hex("0x").unwrap(), []);
+        assert!(from_hex("0x4").is_err());
+        assert!(from_hex("0x0x48").is_err());
+        // Without the prefix, a leading zero byte
+        assert_eq!(from_hex("0048").unwrap(), [0, 0x48]);
+    }
+
+    #[test]
+    fn test_load_hex_lines() {
+        let lines = load_hex_lines("4").unwrap();
+        assert_eq!(lines.len(), 327);
+        let first_line = "0e3647e8592d35514a081243582536ed3de6734059001e3f535ce6271032";
+        assert_eq!(lines[0], from_hex(first_line).unwrap());
+        assert!(load_hex_lines("does_not_exist").is_err());
+        // Base64 data
+        assert!(load_hex_lines("7").is_err());
+    }
+
+    #[test]
+    fn test_to_hex() {
+        assert_eq!(to_hex(b""), "");
+        assert_eq!(to_hex(&[0, 0x0f, 0xab, 0xff]), "000fabff");
+        assert_eq!(
+            from_hex(&to_hex(b"Hello, world!")).unwrap(),
+            b"Hello, world!"
+        );
+    }
+
+    #[test]
+    fn test_hex_to_base64() {
+        let hex_string = "49276d206b696c6c696e6720796f757220627261696e206c696b65206120706f69736f6e6f7573206d757368726f6f6d";
+        let base64_string = "SSdtIGtpbGxpbmcgeW91ciBicmFpbiBsaWtlIGEgcG9pc29ub3VzIG11c2hyb29t";
+        assert_eq!(hex_to_base64(hex_string).unwrap(), base64_string);
+        assert_eq!(base64_to_hex(base64_string).unwrap(), hex_string);
+
+        assert_eq!(hex_to_base64("").unwrap(), "");
+        assert!(hex_to_base64("4927z").is_err());
+        assert!(base64_to_hex("SSdt!").is_err());
+    }
 }
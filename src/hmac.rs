@@ -0,0 +1,104 @@
+use alloc::vec::Vec;
+
+use crate::digest::Digest;
+use crate::sha1::Sha1;
+
+// https://datatracker.ietf.org/doc/html/rfc2104
+pub fn hmac<D: Digest>(key: &[u8], message: &[u8]) -> Vec<u8> {
+    let mut block_key = if key.len() > D::BLOCK_LEN {
+        D::digest(key)
+    } else {
+        key.to_vec()
+    };
+    block_key.resize(D::BLOCK_LEN, 0);
+
+    let mut inner = D::new();
+    inner.update(&block_key.iter().map(|b| b ^ 0x36).collect::<Vec<u8>>());
+    inner.update(message);
+
+    let mut outer = D::new();
+    outer.update(&block_key.iter().map(|b| b ^ 0x5c).collect::<Vec<u8>>());
+    outer.update(&inner.finalize());
+    outer.finalize()
+}
+
+pub fn hmac_sha1(key: &[u8], message: &[u8]) -> [u8; 20] {
+    // The output length of SHA-1 is 20 bytes
+    hmac::<Sha1>(key, message).try_into().unwrap()
+}
+
+/// Compares two MACs in a time that only depends on their length, so that a timing attack
+/// can't recover the expected value byte by byte.
+pub fn constant_time_eq(mac1: &[u8], mac2: &[u8]) -> bool {
+    if mac1.len() != mac2.len() {
+        return false;
+    }
+    mac1.iter().zip(mac2).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::hex::from_hex;
+    use crate::hmac::*;
+    use crate::sha256::Sha256;
+
+    #[test]
+    fn test_hmac_sha1() {
+        // https://datatracker.ietf.org/doc/html/rfc2202#section-3
+        for (key, data, digest) in [
+            (
+                vec![0x0b; 20],
+                b"Hi There".to_vec(),
+                "b617318655057264e28bc0b6fb378c8ef146be00",
+            ),
+            (
+                b"Jefe".to_vec(),
+                b"what do ya want for nothing?".to_vec(),
+                "effcdf6ae5eb2fa2d27416d5f184df9c259a7c79",
+            ),
+            (
+                vec![0xaa; 80],
+                b"Test Using Larger Than Block-Size Key - Hash Key First".to_vec(),
+                "aa4ae5e15272d00e95705637ce8a3b55ed402112",
+            ),
+        ] {
+            assert_eq!(hmac_sha1(&key, &data).to_vec(), from_hex(digest).unwrap());
+            assert_eq!(hmac::<Sha1>(&key, &data), from_hex(digest).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_hmac_sha256() {
+        // https://datatracker.ietf.org/doc/html/rfc4231#section-4
+        for (key, data, digest) in [
+            (
+                vec![0x0b; 20],
+                b"Hi There".to_vec(),
+                "b0344c61d8db38535ca8afceaf0bf12b881dc200c9833da726e9376c2e32cff7",
+            ),
+            (
+                b"Jefe".to_vec(),
+                b"what do ya want for nothing?".to_vec(),
+                "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843",
+            ),
+            (
+                vec![0xaa; 131],
+                b"Test Using Larger Than Block-Size Key - Hash Key First".to_vec(),
+                "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54",
+            ),
+        ] {
+            let mac = hmac::<Sha256>(&key, &data);
+            assert_eq!(mac.len(), Sha256::OUTPUT_LEN);
+            assert_eq!(mac, from_hex(digest).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_constant_time_eq() {
+        assert!(constant_time_eq(b"", b""));
+        assert!(constant_time_eq(b"abc", b"abc"));
+        assert!(!constant_time_eq(b"abc", b"abd"));
+        assert!(!constant_time_eq(b"abc", b"ab"));
+        assert!(!constant_time_eq(&[0x80], &[0]));
+    }
+}
@@ -0,0 +1,84 @@
+use alloc::vec::Vec;
+
+use crate::hmac::hmac_sha1;
+
+/// PBKDF2 with HMAC-SHA1 as the pseudorandom function, returning `len` bytes of key material.
+// https://datatracker.ietf.org/doc/html/rfc8018#section-5.2
+pub fn pbkdf2_hmac_sha1(password: &[u8], salt: &[u8], iterations: u32, len: usize) -> Vec<u8> {
+    let mut derived_key = Vec::with_capacity(len);
+
+    for block_index in 1u32.. {
+        if derived_key.len() >= len {
+            break;
+        }
+        let mut u = hmac_sha1(password, &[salt, &block_index.to_be_bytes()].concat());
+        let mut block = u;
+        for _ in 1..iterations {
+            u = hmac_sha1(password, &u);
+            block.iter_mut().zip(u).for_each(|(b, u)| *b ^= u);
+        }
+        derived_key.extend_from_slice(&block);
+    }
+
+    derived_key.truncate(len);
+    derived_key
+}
+
+/// Derives an AES-128 key from a password
+pub fn derive_key_pbkdf2(password: &[u8], salt: &[u8], iterations: u32) -> [u8; 16] {
+    // The length is right by construction
+    pbkdf2_hmac_sha1(password, salt, iterations, 16)
+        .try_into()
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::hex::from_hex;
+    use crate::kdf::*;
+
+    #[test]
+    fn test_pbkdf2_hmac_sha1() {
+        // https://datatracker.ietf.org/doc/html/rfc6070#section-2
+        for (password, salt, iterations, derived_key) in [
+            (
+                b"password".to_vec(),
+                b"salt".to_vec(),
+                1,
+                "0c60c80f961f0e71f3a9b524af6012062fe037a6",
+            ),
+            (
+                b"password".to_vec(),
+                b"salt".to_vec(),
+                2,
+                "ea6c014dc72d6f8ccd1ed92ace1d41f0d8de8957",
+            ),
+            (
+                b"password".to_vec(),
+                b"salt".to_vec(),
+                4096,
+                "4b007901b765489abead49d926f721d065a429c1",
+            ),
+            (
+                b"passwordPASSWORDpassword".to_vec(),
+                b"saltSALTsaltSALTsaltSALTsaltSALTsalt".to_vec(),
+                4096,
+                "3d2eec4fe41c849b80c8d83662c0e44a8b291a964cf2f07038",
+            ),
+        ] {
+            let expected = from_hex(derived_key).unwrap();
+            assert_eq!(
+                pbkdf2_hmac_sha1(&password, &salt, iterations, expected.len()),
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn test_derive_key_pbkdf2() {
+        assert_eq!(
+            derive_key_pbkdf2(b"password", b"salt", 4096).to_vec(),
+            from_hex("4b007901b765489abead49d926f721d0").unwrap()
+        );
+    }
+}
@@ -0,0 +1,38 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+use alloc::boxed::Box;
+use core::error::Error;
+
+/// Return type of the functions that can fail for several reasons
+pub type BoxResult<T> = Result<T, Box<dyn Error + 'static>>;
+
+#[cfg(feature = "std")]
+pub mod aes;
+#[cfg(feature = "std")]
+pub mod analysis;
+pub mod base64;
+pub mod block;
+#[cfg(feature = "std")]
+pub mod crack;
+pub mod decrypt;
+pub mod digest;
+#[cfg(feature = "std")]
+pub mod encrypt;
+pub mod hex;
+pub mod hmac;
+pub mod kdf;
+pub mod mt19937;
+#[cfg(feature = "std")]
+pub mod profile;
+#[cfg(feature = "std")]
+pub mod set1;
+#[cfg(feature = "std")]
+pub mod set2;
+#[cfg(feature = "std")]
+pub mod set3;
+#[cfg(feature = "std")]
+pub mod set4;
+pub mod sha1;
+pub mod sha256;
@@ -1,14 +1,6 @@
 extern crate env_logger;
 
-mod aes;
-mod base64;
-mod block;
-mod crack;
-mod decrypt;
-mod encrypt;
-mod hex;
-mod set1;
-mod set2;
+use cryptopals_solution::{set1, set2, set3, set4};
 
 fn main() {
     env_logger::init_from_env(
@@ -18,4 +10,8 @@ fn main() {
     set1::run();
 
     set2::run();
+
+    set3::run();
+
+    set4::run();
 }
@@ -0,0 +1,111 @@
+use alloc::vec::Vec;
+
+use crate::block::StreamCipher;
+
+// https://en.wikipedia.org/wiki/Mersenne_Twister
+const N: usize = 624;
+const M: usize = 397;
+const MATRIX_A: u32 = 0x9908B0DF;
+const UPPER_MASK: u32 = 0x8000_0000;
+const LOWER_MASK: u32 = 0x7FFF_FFFF;
+const INIT_MULTIPLIER: u32 = 1812433253;
+
+/// 32-bit Mersenne Twister, as `std::mt19937` in C++ or `random` in Python.
+#[derive(Clone)]
+pub struct Mt19937 {
+    state: [u32; N],
+    // Next word of the state to temper, N when the state must be regenerated
+    index: usize,
+}
+
+impl Mt19937 {
+    pub fn new(seed: u32) -> Mt19937 {
+        let mut state = [0u32; N];
+        state[0] = seed;
+        for i in 1..N {
+            state[i] = INIT_MULTIPLIER
+                .wrapping_mul(state[i - 1] ^ (state[i - 1] >> 30))
+                .wrapping_add(i as u32);
+        }
+        Mt19937 { state, index: N }
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        if self.index >= N {
+            self.twist();
+        }
+        let mut y = self.state[self.index];
+        self.index += 1;
+
+        y ^= y >> 11;
+        y ^= (y << 7) & 0x9D2C5680;
+        y ^= (y << 15) & 0xEFC60000;
+        y ^ (y >> 18)
+    }
+
+    fn twist(&mut self) {
+        for i in 0..N {
+            let y = (self.state[i] & UPPER_MASK) | (self.state[(i + 1) % N] & LOWER_MASK);
+            let mag = if y & 1 == 1 { MATRIX_A } else { 0 };
+            self.state[i] = self.state[(i + M) % N] ^ (y >> 1) ^ mag;
+        }
+        self.index = 0;
+    }
+}
+
+/// Stream cipher of set 3 challenge 24, keyed by a 16-bit seed: the keystream is the low byte
+/// of each MT19937 output.
+#[derive(Debug, Clone, Copy)]
+pub struct Mt19937Cipher {
+    seed: u16,
+}
+
+impl Mt19937Cipher {
+    pub fn new(seed: u16) -> Mt19937Cipher {
+        Mt19937Cipher { seed }
+    }
+}
+
+impl StreamCipher for Mt19937Cipher {
+    fn keystream(&self, len: usize) -> Vec<u8> {
+        let mut rng = Mt19937::new(self.seed.into());
+        (0..len).map(|_| rng.next_u32() as u8).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mt19937::*;
+
+    #[test]
+    fn test_mt19937() {
+        // Default seed of the reference implementation
+        let mut rng = Mt19937::new(5489);
+        assert_eq!(rng.next_u32(), 3499211612);
+        assert_eq!(rng.next_u32(), 581869302);
+        assert_eq!(rng.next_u32(), 3890346734);
+
+        // C++ requires the 10000th output of a default-constructed std::mt19937 to be 4123659995
+        let mut rng = Mt19937::new(5489);
+        assert_eq!((0..10000).map(|_| rng.next_u32()).last(), Some(4123659995));
+
+        let mut rng = Mt19937::new(1);
+        assert_eq!(rng.next_u32(), 1791095845);
+    }
+
+    #[test]
+    fn test_mt19937_cipher() {
+        let cipher = Mt19937Cipher::new(5489);
+        assert_eq!(
+            cipher.keystream(3),
+            [3499211612u32 as u8, 581869302u32 as u8, 3890346734u32 as u8]
+        );
+
+        let plaintext = b"AAAAAAAAAAAAAA known plaintext";
+        let encrypted = cipher.apply(plaintext);
+        assert_ne!(encrypted, plaintext);
+        assert_eq!(cipher.apply(&encrypted), plaintext);
+        assert!(cipher.apply(b"").is_empty());
+        assert_ne!(Mt19937Cipher::new(5490).apply(&encrypted), plaintext);
+    }
+}
@@ -0,0 +1,82 @@
+use std::collections::HashMap;
+
+use rand::Rng;
+
+use crate::aes::{AesEcb, Cipher};
+use crate::BoxResult;
+
+/// Parses `foo=bar&baz=qux`, a key without `=` gets an empty value
+pub fn parse_key_value(data: &str) -> HashMap<String, String> {
+    data.split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (key.to_string(), value.to_string())
+        })
+        .collect()
+}
+
+/// Encoded user profile, the metacharacters `&` and `=` are removed from the email
+pub fn profile_for(email: &str) -> String {
+    let email: String = email.chars().filter(|c| *c != '&' && *c != '=').collect();
+    format!("email={}&uid=10&role=user", email)
+}
+
+/// Challenge 13 oracle, encrypting the user profiles with ECB under a key drawn once
+pub struct Challenge13Oracle {
+    cipher: AesEcb,
+}
+
+impl Challenge13Oracle {
+    pub fn new() -> BoxResult<Challenge13Oracle> {
+        Ok(Challenge13Oracle {
+            cipher: AesEcb::new(&rand::thread_rng().gen())?,
+        })
+    }
+
+    pub fn encrypt_profile(&self, email: &str) -> BoxResult<Vec<u8>> {
+        self.cipher.encrypt(profile_for(email).as_bytes())
+    }
+
+    pub fn decrypt_and_parse(&self, ciphertext: &[u8]) -> BoxResult<HashMap<String, String>> {
+        let profile = String::from_utf8(self.cipher.decrypt(ciphertext)?)?;
+        Ok(parse_key_value(&profile))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::profile::*;
+
+    #[test]
+    fn test_profile_for() {
+        assert_eq!(
+            profile_for("foo@bar.com"),
+            "email=foo@bar.com&uid=10&role=user"
+        );
+        assert_eq!(
+            profile_for("foo@bar.com&role=admin"),
+            "email=foo@bar.comroleadmin&uid=10&role=user"
+        );
+
+        let profile = parse_key_value("foo=bar&baz=qux&zap=zazzle");
+        assert_eq!(profile.len(), 3);
+        assert_eq!(profile["baz"], "qux");
+        assert_eq!(parse_key_value("a&b=c=d")["a"], "");
+        assert_eq!(parse_key_value("a&b=c=d")["b"], "c=d");
+        assert!(parse_key_value("").is_empty());
+    }
+
+    #[test]
+    fn test_challenge13_oracle() {
+        let oracle = Challenge13Oracle::new().unwrap();
+        let ciphertext = oracle.encrypt_profile("foo@bar.com").unwrap();
+        let profile = oracle.decrypt_and_parse(&ciphertext).unwrap();
+        assert_eq!(profile["role"], "user");
+        assert_eq!(profile["uid"], "10");
+
+        // Another oracle has another key
+        let other = Challenge13Oracle::new().unwrap();
+        assert_ne!(other.encrypt_profile("foo@bar.com").unwrap(), ciphertext);
+    }
+}
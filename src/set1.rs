@@ -3,21 +3,23 @@ use std::io::Read;
 
 use log::info;
 
-use crate::aes::{decrypt_ecb, encrypt_ecb};
-use crate::base64::{from_base64, load_base64_file, to_base64};
-use crate::block::{xor, BlockSize};
+use crate::aes::{decrypt_ecb_file, encrypt_ecb};
+use crate::analysis::find_ecb_line;
+use crate::base64::{data_dir, from_base64, load_base64_file};
+use crate::block::xor;
 use crate::decrypt::{
-    break_xor_single_char, find_key_block_xor, find_likely_xor_keysizes, hamming_distance,
-    EnglishWordFreq,
+    break_xor_single_char, detect_single_char_xor, find_key_block_xor, find_likely_xor_keysizes,
+    hamming_distance, DecodingResult, EnglishWordFreq,
 };
 use crate::encrypt::encode_xor;
-use crate::hex::from_hex;
+use crate::hex::{from_hex, hex_to_base64, load_hex_lines};
+use crate::BoxResult;
 
 pub fn run() {
     info!("Set1 Challenge 1");
 
     assert_eq!(
-            to_base64(&from_hex("49276d206b696c6c696e6720796f757220627261696e206c696b65206120706f69736f6e6f7573206d757368726f6f6d").unwrap()),
+            hex_to_base64("49276d206b696c6c696e6720796f757220627261696e206c696b65206120706f69736f6e6f7573206d757368726f6f6d").unwrap(),
             "SSdtIGtpbGxpbmcgeW91ciBicmFpbiBsaWtlIGEgcG9pc29ub3VzIG11c2hyb29t"
         );
 
@@ -49,24 +51,11 @@ pub fn run() {
 
     info!("Set1 Challenge 4");
 
-    let mut data = String::new();
-    File::open("data/4.txt")
-        .and_then(|mut f| f.read_to_string(&mut data))
-        .unwrap();
-
-    let mut max_score = f32::MIN;
-    let mut best_line = String::new();
-
-    for line in data.split('\n') {
-        let decoded = break_xor_single_char::<EnglishWordFreq>(&from_hex(line).unwrap());
-        if let Some(decoded) = decoded {
-            if decoded.score > max_score {
-                max_score = decoded.score;
-                best_line = String::from_utf8(decoded.decoded_content).unwrap();
-            }
-        }
-    }
-    assert_eq!(best_line, "Now that the party is jumping\n");
+    let (_, decoded) = solve_challenge4("4").unwrap();
+    assert_eq!(
+        String::from_utf8(decoded.decoded_content).unwrap(),
+        "Now that the party is jumping\n"
+    );
 
     info!("Set1 Challenge 5");
 
@@ -192,7 +181,7 @@ pub fn run() {
     info!("Set1 Challenge 7");
 
     let ciphertext = load_base64_file("7").unwrap();
-    let plaintext = decrypt_ecb(&ciphertext, b"YELLOW SUBMARINE").unwrap();
+    let plaintext = decrypt_ecb_file("7", b"YELLOW SUBMARINE").unwrap();
 
     let ciphertext_2 = encrypt_ecb(&plaintext, b"YELLOW SUBMARINE").unwrap();
 
@@ -284,7 +273,7 @@ pub fn run() {
     info!("Set1 Challenge 8");
 
     let mut data = String::new();
-    File::open("data/8.txt")
+    File::open(data_dir().join("8.txt"))
         .and_then(|mut f| f.read_to_string(&mut data))
         .unwrap();
 
@@ -292,29 +281,37 @@ pub fn run() {
     // Therefore if we find 2 identical cyphers then it is very, very, very likely that
     // this is because we are using ECB, other encoding schemes should return pseudo-random
     // cyphers which are very unlikely to match
-    let mut found: Option<&str> = None;
-    for line in data.trim().split('\n') {
-        let decoded = from_base64(line).unwrap();
-        let li = decoded
-            .as_slice()
-            .chunks(BlockSize::AES_BLK_SZ_USIZE)
-            .collect::<Vec<&[u8]>>();
-        for (index, val1) in li.iter().enumerate() {
-            for val2 in &li[..index] {
-                if val1 == val2 {
-                    found = Some(line);
-                };
-            }
-        }
-    }
+    let lines: Vec<&str> = data.trim().split('\n').collect();
+    let decoded: Vec<Vec<u8>> = lines
+        .iter()
+        .map(|line| from_base64(line).unwrap())
+        .collect();
+    let found = find_ecb_line(&decoded).map(|(index, _)| lines[index]);
     assert_eq!(found, Some("d880619740a8a19b7840a8a31c810a3d08649af70dc06f4fd5d2d69c744cd283e2dd052f6b641dbf9d11b0348542bb5708649af70dc06f4fd5d2d69c744cd2839475c9dfdbc1d46597949d9c7e82bf5a08649af70dc06f4fd5d2d69c744cd28397a93eab8d6aecd566489154789a6b0308649af70dc06f4fd5d2d69c744cd283d403180c98c8f6db1f2a3f9c4040deb0ab51b29933f2c123c58386b06fba186a"));
 }
 
+/// Finds the line of the hex data file encrypted with a single-byte XOR, returns its index and
+/// its decryption.
+pub fn solve_challenge4(file_id: &str) -> BoxResult<(usize, DecodingResult)> {
+    let lines = load_hex_lines(file_id)?;
+    detect_single_char_xor::<EnglishWordFreq>(&lines)
+        .ok_or_else(|| "No line looks like English encrypted with a single byte".into())
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::set1::run;
+    use crate::set1::*;
     #[test]
     fn test() {
         run();
     }
+
+    #[test]
+    fn test_solve_challenge4() {
+        let (line, decoded) = solve_challenge4("4").unwrap();
+        assert_eq!(line, 170);
+        assert_eq!(decoded.key, b'5');
+        assert_eq!(decoded.decoded_content, b"Now that the party is jumping\n");
+        assert!(solve_challenge4("does_not_exist").is_err());
+    }
 }
@@ -1,10 +1,12 @@
 use log::info;
-use rand::Rng;
 
-use crate::aes::{decrypt_cbc, encrypt_cbc, encrypt_ecb, oracle, unknown_encryption, Protocol};
+use crate::aes::{
+    decrypt_cbc, ecb_append_oracle, oracle, roundtrip_cbc, unknown_encryption, Protocol,
+};
 use crate::base64::{from_base64, load_base64_file};
 use crate::block::{add_padding, BlockSize};
-use crate::crack::crack_ecb;
+use crate::crack::{crack_ecb, forge_admin_profile};
+use crate::profile::Challenge13Oracle;
 
 pub fn run() {
     info!("Set2 Challenge 9");
@@ -20,10 +22,8 @@ pub fn run() {
     let iv = [0; 16];
     let key = b"YELLOW SUBMARINE";
 
+    assert!(roundtrip_cbc(&ciphertext, &iv, key).unwrap());
     let plaintext = decrypt_cbc(&ciphertext, &iv, key).unwrap();
-    let ciphertext_from_plain = encrypt_cbc(&plaintext, &iv, key).unwrap();
-
-    assert_eq!(ciphertext_from_plain, ciphertext);
 
     assert_eq!(
         String::from_utf8(plaintext).unwrap(),
@@ -132,26 +132,24 @@ pub fn run() {
     )
     .unwrap();
 
-    let mut rng = rand::thread_rng();
-
-    let unknown_key: [u8; 16] = rng.gen();
-    let random_ecb = |data: &[u8]| {
-        return encrypt_ecb(
-            [data, secret_message.as_slice()].concat().as_slice(),
-            &unknown_key,
-        );
-    };
-
-    let extracted_message = crack_ecb(random_ecb).unwrap();
+    let extracted_message = crack_ecb(ecb_append_oracle(secret_message.clone())).unwrap();
     assert_eq!(
         String::from_utf8(extracted_message).unwrap(),
         String::from_utf8(secret_message).unwrap()
     );
+
+    info!("Set2 Challenge 13");
+
+    let profile_oracle = Challenge13Oracle::new().unwrap();
+    let forged = forge_admin_profile(&profile_oracle).unwrap();
+    let profile = profile_oracle.decrypt_and_parse(&forged).unwrap();
+    assert_eq!(profile["role"], "admin");
+    assert_eq!(profile["email"], "foo@baar.coom");
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::set2::run;
+    use crate::set2::*;
     #[test]
     fn test() {
         run();
@@ -0,0 +1,36 @@
+use log::info;
+
+use crate::aes::{ctr, CounterEndian};
+use crate::base64::from_base64;
+use crate::mt19937::Mt19937;
+
+pub fn run() {
+    info!("Set3 Challenge 18");
+
+    let ciphertext =
+        from_base64("L77na/nrFsKvynd6HzOoG7GHTLXsTVu9qvY/2syLXzhPweyyMTJULu/6/kXX0KSvoOLSFQ==")
+            .unwrap();
+    let plaintext = ctr(&ciphertext, b"YELLOW SUBMARINE", 0, CounterEndian::Little).unwrap();
+    assert_eq!(
+        String::from_utf8(plaintext).unwrap(),
+        "Yo, VIP Let's kick it Ice, Ice, baby Ice, Ice, baby "
+    );
+
+    info!("Set3 Challenge 21");
+
+    let mut rng = Mt19937::new(5489);
+    let outputs: Vec<u32> = (0..5).map(|_| rng.next_u32()).collect();
+    assert_eq!(
+        outputs,
+        [3499211612, 581869302, 3890346734, 3586334585, 545404204]
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::set3::run;
+    #[test]
+    fn test() {
+        run();
+    }
+}
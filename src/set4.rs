@@ -0,0 +1,31 @@
+use log::info;
+use rand::Rng;
+
+use crate::sha1::sha1;
+
+pub fn run() {
+    info!("Set4 Challenge 28");
+
+    let mut rng = rand::thread_rng();
+    let key: [u8; 16] = rng.gen();
+    let secret_prefix_mac = |message: &[u8]| sha1(&[&key, message].concat());
+
+    let message = b"comment1=cooking%20MCs;userdata=foo;comment2=%20like%20a%20pound%20of%20bacon";
+    let mac = secret_prefix_mac(message);
+    assert_eq!(secret_prefix_mac(message), mac);
+
+    // Tampering with the message, or hashing it without the key, gives another MAC
+    let mut tampered = message.to_vec();
+    tampered[37] ^= 1;
+    assert_ne!(secret_prefix_mac(&tampered), mac);
+    assert_ne!(sha1(message), mac);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::set4::run;
+    #[test]
+    fn test() {
+        run();
+    }
+}
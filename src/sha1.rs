@@ -0,0 +1,179 @@
+use alloc::{vec, vec::Vec};
+#[cfg(feature = "std")]
+use std::io::{self, Write};
+
+use crate::digest::Digest;
+
+// https://datatracker.ietf.org/doc/html/rfc3174
+const INITIAL_STATE: [u32; 5] = [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0];
+const BLOCK_SIZE: usize = 64;
+
+/// Incremental SHA-1. Data can be fed with `update` or through `io::Write`, and the digest
+/// is obtained with `finalize`.
+#[derive(Clone)]
+pub struct Sha1 {
+    state: [u32; 5],
+    // Data not yet processed, always shorter than a block
+    buffer: Vec<u8>,
+    // Total length of the message in bytes
+    length: u64,
+}
+
+impl Default for Sha1 {
+    fn default() -> Self {
+        Sha1::new()
+    }
+}
+
+impl Sha1 {
+    pub fn new() -> Sha1 {
+        Sha1 {
+            state: INITIAL_STATE,
+            buffer: Vec::with_capacity(BLOCK_SIZE),
+            length: 0,
+        }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        self.length += data.len() as u64;
+        self.buffer.extend_from_slice(data);
+
+        let full_blocks = self.buffer.len() - self.buffer.len() % BLOCK_SIZE;
+        for block in self.buffer[..full_blocks].chunks_exact(BLOCK_SIZE) {
+            compress(&mut self.state, block.try_into().unwrap());
+        }
+        self.buffer.drain(..full_blocks);
+    }
+
+    pub fn finalize(mut self) -> [u8; 20] {
+        let bit_length = self.length * 8;
+
+        // 0x80, then zeros until there are only 8 bytes left in the block for the length
+        let padding_len = BLOCK_SIZE - (self.buffer.len() + 8) % BLOCK_SIZE;
+        let mut padding = vec![0u8; padding_len];
+        padding[0] = 0x80;
+        self.update(&padding);
+        self.update(&bit_length.to_be_bytes());
+
+        let mut digest = [0u8; 20];
+        for (chunk, word) in digest.chunks_exact_mut(4).zip(self.state) {
+            chunk.copy_from_slice(&word.to_be_bytes());
+        }
+        digest
+    }
+}
+
+#[cfg(feature = "std")]
+impl Write for Sha1 {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Digest for Sha1 {
+    const OUTPUT_LEN: usize = 20;
+    const BLOCK_LEN: usize = BLOCK_SIZE;
+
+    fn new() -> Self {
+        Sha1::new()
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        Sha1::update(self, data)
+    }
+
+    fn finalize(self) -> Vec<u8> {
+        Sha1::finalize(self).to_vec()
+    }
+}
+
+fn compress(state: &mut [u32; 5], block: &[u8; BLOCK_SIZE]) {
+    let mut w = [0u32; 80];
+    for (word, chunk) in w.iter_mut().zip(block.chunks_exact(4)) {
+        *word = u32::from_be_bytes(chunk.try_into().unwrap());
+    }
+    for t in 16..80 {
+        w[t] = (w[t - 3] ^ w[t - 8] ^ w[t - 14] ^ w[t - 16]).rotate_left(1);
+    }
+
+    let [mut a, mut b, mut c, mut d, mut e] = *state;
+    for (t, word) in w.iter().enumerate() {
+        let (f, k) = match t {
+            0..=19 => ((b & c) | (!b & d), 0x5A827999),
+            20..=39 => (b ^ c ^ d, 0x6ED9EBA1),
+            40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1BBCDC),
+            _ => (b ^ c ^ d, 0xCA62C1D6),
+        };
+        let temp = a
+            .rotate_left(5)
+            .wrapping_add(f)
+            .wrapping_add(e)
+            .wrapping_add(k)
+            .wrapping_add(*word);
+        e = d;
+        d = c;
+        c = b.rotate_left(30);
+        b = a;
+        a = temp;
+    }
+
+    for (s, v) in state.iter_mut().zip([a, b, c, d, e]) {
+        *s = s.wrapping_add(v);
+    }
+}
+
+pub fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut hasher = Sha1::new();
+    hasher.update(data);
+    hasher.finalize()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::hex::from_hex;
+    use crate::sha1::*;
+
+    #[test]
+    fn test_sha1() {
+        for (data, digest) in [
+            (b"".to_vec(), "da39a3ee5e6b4b0d3255bfef95601890afd80709"),
+            (b"abc".to_vec(), "a9993e364706816aba3e25717850c26c9cd0d89d"),
+            (
+                b"The quick brown fox jumps over the lazy dog".to_vec(),
+                "2fd4e1c67a2d28fced849ee1bb76e7391b93eb12",
+            ),
+            (
+                b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq".to_vec(),
+                "84983e441c3bd26ebaae4aa1f95129e5e54670f1",
+            ),
+            (
+                b"a".repeat(1000),
+                "291e9a6c66994949b57ba5e650361e98fc36b1ba",
+            ),
+        ] {
+            assert_eq!(sha1(&data).to_vec(), from_hex(digest).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_sha1_write() {
+        let message = b"The quick brown fox jumps over the lazy dog".repeat(5);
+
+        for chunk_size in [1, 3, 63, 64, 65, 100] {
+            let mut hasher = Sha1::new();
+            for chunk in message.chunks(chunk_size) {
+                hasher.write_all(chunk).unwrap();
+            }
+            assert_eq!(hasher.finalize(), sha1(&message));
+        }
+
+        let mut hasher = Sha1::new();
+        io::copy(&mut message.as_slice(), &mut hasher).unwrap();
+        assert_eq!(hasher.finalize(), sha1(&message));
+    }
+}